@@ -0,0 +1,28 @@
+use super::{AgentProvider, ProviderCapabilities};
+
+pub const ID: &str = "claude-code";
+
+/// Claude Code through the `claude-code-acp` adapter.
+pub struct ClaudeCodeProvider;
+
+impl AgentProvider for ClaudeCodeProvider {
+    fn id(&self) -> &'static str {
+        ID
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Claude Code"
+    }
+
+    fn binary_name(&self) -> &'static str {
+        "claude-code-acp"
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            load_session: true,
+            image_prompts: true,
+            session_modes: true,
+        }
+    }
+}
@@ -1,279 +1,28 @@
-use std::{
-    path::PathBuf,
-    sync::{Arc, OnceLock},
-    thread,
-};
+use super::{AgentProvider, ProviderCapabilities};
 
-use crate::event_bus::{self, AgentEvent};
-use agent_client_protocol::{self as acp, Agent};
-use tokio::{
-    process::Command,
-    runtime::Builder,
-    task::LocalSet,
-    time::{sleep, Duration},
-};
-use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+pub const ID: &str = "codex";
 
-use crate::acp_client::client::AcpClient;
+/// OpenAI Codex through the `codex-acp` adapter.
+pub struct CodexProvider;
 
-struct AgentWorker {
-    sender: tokio::sync::mpsc::UnboundedSender<WorkerRequest>,
-}
-
-enum WorkerRequest {
-    NewSession {
-        workspace: Option<String>,
-        reply: tokio::sync::oneshot::Sender<Result<String, String>>,
-    },
-    Prompt {
-        session_id: Option<String>,
-        message: String,
-        reply: tokio::sync::oneshot::Sender<Result<String, String>>,
-    },
-}
-
-static AGENT_WORKER: OnceLock<Result<AgentWorker, String>> = OnceLock::new();
-
-/// Sends a prompt to the codex ACP agent and returns the accumulated text response.
-pub async fn send_codex_message(
-    message: String,
-    session_id: Option<String>,
-) -> Result<String, String> {
-    if message.trim().is_empty() {
-        return Err("Message cannot be empty".into());
+impl AgentProvider for CodexProvider {
+    fn id(&self) -> &'static str {
+        ID
     }
 
-    let worker = AGENT_WORKER
-        .get_or_init(start_worker)
-        .as_ref()
-        .map_err(|err| err.clone())?;
-
-    let (tx, rx) = tokio::sync::oneshot::channel();
-    worker
-        .sender
-        .send(WorkerRequest::Prompt {
-            session_id,
-            message,
-            reply: tx,
-        })
-        .map_err(|err| format!("Agent channel send failed: {err}"))?;
-
-    rx.await
-        .map_err(|err| format!("Agent channel closed: {err}"))?
-}
-
-/// Starts a new session with the given workspace directory.
-/// If workspace is None, uses the current working directory.
-pub async fn new_codex_session(workspace: Option<String>) -> Result<String, String> {
-    let worker = AGENT_WORKER
-        .get_or_init(start_worker)
-        .as_ref()
-        .map_err(|err| {
-            println!("new_codex_session error: {err}");
-            err.clone()
-        })?;
-
-    let (tx, rx) = tokio::sync::oneshot::channel();
-    worker
-        .sender
-        .send(WorkerRequest::NewSession {
-            workspace,
-            reply: tx,
-        })
-        .map_err(|err| format!("Agent channel send failed: {err}"))?;
-
-    rx.await
-        .map_err(|err| format!("Agent channel closed: {err}"))?
-}
-
-fn start_worker() -> Result<AgentWorker, String> {
-    // let agent_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("agents/codex/codex-acp");
-    let agent_path = PathBuf::from("/opt/homebrew/bin/qwen");
-    if !agent_path.exists() {
-        return Err(format!(
-            "Agent binary not found at {}",
-            agent_path.display()
-        ));
+    fn display_name(&self) -> &'static str {
+        "Codex"
     }
 
-    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
-
-    thread::spawn(move || {
-        let runtime = match Builder::new_current_thread().enable_all().build() {
-            Ok(rt) => rt,
-            Err(err) => {
-                let _ = ready_tx.send(Err(format!("Failed to build runtime: {err}")));
-                return;
-            }
-        };
-
-        let local = LocalSet::new();
-        let worker_future = async move {
-            let cwd = std::env::current_dir()
-                .and_then(|path| path.canonicalize())
-                .map_err(|err| format!("Failed to resolve current directory: {err}"))?;
-
-            let mut child = Command::new("sh")
-                .arg("-c")
-                .arg("qwen --acp --yolo")
-                .stdin(std::process::Stdio::piped())
-                .stdout(std::process::Stdio::piped())
-                .stderr(std::process::Stdio::inherit())
-                .spawn()
-                .map_err(|err| format!("Failed to start agent: {err}"))?;
-
-            let stdout = child
-                .stdout
-                .take()
-                .ok_or_else(|| "Failed to capture agent stdout".to_string())?
-                .compat();
-            let stdin = child
-                .stdin
-                .take()
-                .ok_or_else(|| "Failed to capture agent stdin".to_string())?
-                .compat_write();
-
-            let output = Arc::new(tokio::sync::Mutex::new(String::new()));
-            let client = AcpClient::new(output.clone());
-            let client_arc = Arc::new(client);
-
-            let (agent_conn, io_task) =
-                acp::ClientSideConnection::new(client_arc.clone(), stdin, stdout, |fut| {
-                    tokio::task::spawn_local(fut);
-                });
-            tokio::task::spawn_local(io_task);
-
-            agent_conn
-                .initialize(
-                    acp::InitializeRequest::new(acp::ProtocolVersion::LATEST).client_info(
-                        acp::Implementation::new("open-cowork", env!("CARGO_PKG_VERSION"))
-                            .title("Open Cowork Client"),
-                    ),
-                )
-                .await
-                .map_err(|err| format!("initialize failed: {err}"))?;
-
-            let session = agent_conn
-                .new_session(acp::NewSessionRequest::new(cwd.clone()))
-                .await
-                .map_err(|err| format!("new_session failed: {err}"))?;
-
-            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<WorkerRequest>();
-            let _ = ready_tx.send(Ok(tx.clone()));
-            let mut default_session = Some(session.session_id.clone());
-
-            while let Some(request) = rx.recv().await {
-                match request {
-                    WorkerRequest::NewSession { workspace, reply } => {
-                        let workspace_path = if let Some(w) = workspace {
-                            PathBuf::from(w)
-                        } else {
-                            cwd.clone()
-                        };
-
-                        // Validate workspace directory exists
-                        if !workspace_path.exists() {
-                            let _ = reply.send(Err(format!(
-                                "Workspace directory does not exist: {}",
-                                workspace_path.display()
-                            )));
-                            continue;
-                        }
-
-                        // Update client workspace before creating session
-                        client_arc.set_workspace(workspace_path.clone()).await;
-
-                        let new_session = agent_conn
-                            .new_session(acp::NewSessionRequest::new(workspace_path))
-                            .await
-                            .map_err(|err| format!("new_session failed: {err}"));
-
-                        if let Ok(ref session) = new_session {
-                            default_session = Some(session.session_id.clone());
-                        }
-
-                        let _ =
-                            reply.send(new_session.map(|s| s.session_id.0.as_ref().to_string()));
-                    }
-                    WorkerRequest::Prompt {
-                        session_id,
-                        message,
-                        reply,
-                    } => {
-                        let target_session = match session_id {
-                            Some(id) => acp::SessionId::new(id),
-                            None => {
-                                if let Some(existing) = default_session.clone() {
-                                    existing
-                                } else {
-                                    let new_session = agent_conn
-                                        .new_session(acp::NewSessionRequest::new(cwd.clone()))
-                                        .await
-                                        .map_err(|err| format!("new_session failed: {err}"));
-                                    match new_session {
-                                        Ok(session) => {
-                                            default_session = Some(session.session_id.clone());
-                                            session.session_id
-                                        }
-                                        Err(err) => {
-                                            let _ = reply.send(Err(err));
-                                            continue;
-                                        }
-                                    }
-                                }
-                            }
-                        };
-
-                        let session_id_str = target_session.0.as_ref().to_string();
-                        client_arc.set_current_session_id(Some(session_id_str.clone())).await;
-
-                        event_bus::emit_event(AgentEvent::Status {
-                            session_id: session_id_str.clone(),
-                            status: "Thinking...".to_string(),
-                        });
-
-                        let prompt = vec![acp::ContentBlock::Text(acp::TextContent::new(message))];
-
-                        {
-                            let mut guard = output.lock().await;
-                            guard.clear();
-                        }
-
-                        let result = agent_conn
-                            .prompt(acp::PromptRequest::new(target_session.clone(), prompt))
-                            .await
-                            .map_err(|err| format!("prompt failed: {err}"));
-
-                        client_arc.set_current_session_id(None).await;
-                        event_bus::emit_event(AgentEvent::Status {
-                            session_id: session_id_str,
-                            status: "idle".to_string(),
-                        });
-
-                        if result.is_ok() {
-                            sleep(Duration::from_millis(120)).await;
-                        }
-
-                        let response_text = {
-                            let mut guard = output.lock().await;
-                            std::mem::take(&mut *guard)
-                        };
-
-                        let _ = reply.send(result.map(|_| response_text));
-                    }
-                }
-            }
-
-            let _ = child.kill().await;
-            Ok::<(), String>(())
-        };
-
-        let _ = local.block_on(&runtime, worker_future);
-    });
+    fn binary_name(&self) -> &'static str {
+        "codex-acp"
+    }
 
-    match ready_rx.recv() {
-        Ok(Ok(sender)) => Ok(AgentWorker { sender }),
-        Ok(Err(err)) => Err(err),
-        Err(_) => Err("Agent worker failed to start".into()),
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            load_session: false,
+            image_prompts: true,
+            session_modes: true,
+        }
     }
 }
@@ -0,0 +1,32 @@
+use super::{AgentProvider, ProviderCapabilities};
+
+pub const ID: &str = "gemini";
+
+/// Gemini CLI in its experimental ACP mode.
+pub struct GeminiProvider;
+
+impl AgentProvider for GeminiProvider {
+    fn id(&self) -> &'static str {
+        ID
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Gemini CLI"
+    }
+
+    fn binary_name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn args(&self) -> Vec<String> {
+        vec!["--experimental-acp".into()]
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            load_session: true,
+            image_prompts: true,
+            session_modes: false,
+        }
+    }
+}
@@ -1 +1,159 @@
+pub mod claude_code;
 pub mod codex;
+pub mod gemini;
+pub mod qwen;
+pub mod worker;
+
+use std::{path::PathBuf, sync::OnceLock};
+
+use serde::Serialize;
+
+/// Provider used when a session is created without an explicit provider id.
+pub const DEFAULT_PROVIDER_ID: &str = codex::ID;
+
+/// Static hints about what an ACP adapter is expected to support.
+/// The authoritative capabilities are still negotiated during `initialize`.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ProviderCapabilities {
+    pub load_session: bool,
+    pub image_prompts: bool,
+    pub session_modes: bool,
+}
+
+/// An ACP-speaking agent that can be spawned as a child process.
+pub trait AgentProvider: Send + Sync {
+    fn id(&self) -> &'static str;
+
+    fn display_name(&self) -> &'static str;
+
+    /// Executable name looked up on `PATH` and in the usual install locations.
+    fn binary_name(&self) -> &'static str;
+
+    /// Prefers a binary bundled under `agents/<id>/` over one found on `PATH`.
+    fn binary_path(&self) -> Result<PathBuf, String> {
+        let bundled = bundled_agent_dirs()
+            .into_iter()
+            .map(|dir| dir.join(self.id()).join(self.binary_name()))
+            .find(|candidate| candidate.is_file());
+        match bundled {
+            Some(bundled) => Ok(bundled),
+            None => resolve_binary(self.binary_name()),
+        }
+    }
+
+    fn args(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn env(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities;
+}
+
+/// Provider description sent to the frontend.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderInfo {
+    pub id: String,
+    pub display_name: String,
+    pub binary_path: Option<String>,
+    pub available: bool,
+    pub capabilities: ProviderCapabilities,
+}
+
+static PROVIDERS: &[&dyn AgentProvider] = &[
+    &codex::CodexProvider,
+    &qwen::QwenProvider,
+    &gemini::GeminiProvider,
+    &claude_code::ClaudeCodeProvider,
+];
+
+pub fn find_provider(id: &str) -> Result<&'static dyn AgentProvider, String> {
+    PROVIDERS
+        .iter()
+        .copied()
+        .find(|provider| provider.id() == id)
+        .ok_or_else(|| format!("Unknown agent provider: {id}"))
+}
+
+pub fn list_providers() -> Vec<ProviderInfo> {
+    PROVIDERS
+        .iter()
+        .map(|provider| {
+            let binary_path = provider.binary_path().ok();
+            ProviderInfo {
+                id: provider.id().to_string(),
+                display_name: provider.display_name().to_string(),
+                available: binary_path.is_some(),
+                binary_path: binary_path.map(|path| path.to_string_lossy().to_string()),
+                capabilities: provider.capabilities(),
+            }
+        })
+        .collect()
+}
+
+static RESOURCE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the app's resource directory, where bundled agents are looked up
+/// first. Called once during setup.
+pub fn init(resource_dir: PathBuf) {
+    let _ = RESOURCE_DIR.set(resource_dir);
+}
+
+/// Directories holding bundled agents as `<id>/<binary>`: the app's
+/// resources, then next to the executable. Debug builds also use the source
+/// tree's `agents/`.
+fn bundled_agent_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = RESOURCE_DIR
+        .get()
+        .map(|dir| dir.join("agents"))
+        .into_iter()
+        .collect();
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(PathBuf::from))
+    {
+        dirs.push(exe_dir.join("agents"));
+    }
+    #[cfg(debug_assertions)]
+    dirs.push(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("agents"));
+    dirs
+}
+
+/// GUI apps on macOS do not inherit the login shell `PATH`, so we also probe
+/// the directories package managers usually install agent CLIs into.
+fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect())
+        .unwrap_or_default();
+
+    dirs.push(PathBuf::from("/opt/homebrew/bin"));
+    dirs.push(PathBuf::from("/usr/local/bin"));
+    if let Some(home) = std::env::var_os("HOME") {
+        let home = PathBuf::from(home);
+        dirs.push(home.join(".local/bin"));
+        dirs.push(home.join(".cargo/bin"));
+        dirs.push(home.join(".npm-global/bin"));
+    }
+    dirs
+}
+
+pub fn resolve_binary(name: &str) -> Result<PathBuf, String> {
+    search_dirs()
+        .into_iter()
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| format!("Agent binary `{name}` not found on PATH"))
+}
+
+/// `PATH` for the agent process: the binary's own directory first so that
+/// node-based adapters can find the `node` installed next to them.
+pub(crate) fn child_path_env(binary: &std::path::Path) -> Option<std::ffi::OsString> {
+    let mut dirs = Vec::new();
+    if let Some(parent) = binary.parent() {
+        dirs.push(parent.to_path_buf());
+    }
+    dirs.extend(search_dirs());
+    std::env::join_paths(dirs).ok()
+}
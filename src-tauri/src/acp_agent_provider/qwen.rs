@@ -0,0 +1,32 @@
+use super::{AgentProvider, ProviderCapabilities};
+
+pub const ID: &str = "qwen";
+
+/// Qwen Code, which speaks ACP natively behind `--acp`.
+pub struct QwenProvider;
+
+impl AgentProvider for QwenProvider {
+    fn id(&self) -> &'static str {
+        ID
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Qwen Code"
+    }
+
+    fn binary_name(&self) -> &'static str {
+        "qwen"
+    }
+
+    fn args(&self) -> Vec<String> {
+        vec!["--acp".into()]
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            load_session: true,
+            image_prompts: true,
+            session_modes: true,
+        }
+    }
+}
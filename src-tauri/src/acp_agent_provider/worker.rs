@@ -0,0 +1,1568 @@
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet, VecDeque},
+    future::Future,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::event_bus::{self, AgentEvent};
+use crate::{
+    attachments::{self, Attachment},
+    checkpoints, file_watcher, hooks, settings,
+    workspace_config::{self, WorkspaceConfig},
+};
+use agent_client_protocol::{self as acp, Agent};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::{Child, ChildStderr, Command},
+    runtime::Builder,
+    sync::{mpsc, oneshot, watch},
+    task::{JoinHandle, LocalSet},
+    time::{sleep, Duration, Instant},
+};
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+use super::{child_path_env, find_provider, AgentProvider, DEFAULT_PROVIDER_ID};
+use crate::acp_client::{
+    client::{AcpClient, Replay},
+    mcp, models, modes, permission, turn_state,
+};
+
+struct AgentWorker {
+    sender: tokio::sync::mpsc::UnboundedSender<WorkerRequest>,
+    launch: AgentLaunch,
+}
+
+/// Arguments and environment a workspace's configuration adds to the agent
+/// process. Sessions only share a process when they launch it the same way.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+struct AgentLaunch {
+    args: Vec<String>,
+    env: BTreeMap<String, String>,
+}
+
+impl AgentLaunch {
+    /// The configured arguments only apply to the configured provider.
+    fn new(provider: &dyn AgentProvider, config: &WorkspaceConfig) -> Self {
+        let args = if config
+            .agent
+            .provider
+            .as_deref()
+            .is_none_or(|id| id == provider.id())
+        {
+            config.agent.args.clone()
+        } else {
+            Vec::new()
+        };
+        Self {
+            args,
+            env: config.env.clone(),
+        }
+    }
+
+    /// The launch for a session in `workspace`. An invalid configuration is
+    /// reported when the session is resumed, so it is ignored here, as is
+    /// one the user hasn't approved.
+    fn for_workspace(provider: &dyn AgentProvider, workspace: Option<&str>) -> Self {
+        workspace
+            .and_then(|workspace| workspace_config::load_approved(Path::new(workspace)).ok())
+            .map(|config| Self::new(provider, &config))
+            .unwrap_or_default()
+    }
+
+    /// Key of the shared worker for this launch
+    fn worker_key(&self, provider: &dyn AgentProvider) -> String {
+        if *self == Self::default() {
+            return provider.id().to_string();
+        }
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        format!("{}@{:016x}", provider.id(), hasher.finish())
+    }
+}
+
+enum WorkerRequest {
+    NewSession {
+        workspace: Option<String>,
+        model_id: Option<String>,
+        mode_id: Option<String>,
+        reply: tokio::sync::oneshot::Sender<Result<String, String>>,
+    },
+    Prompt {
+        session_id: Option<String>,
+        resume: ResumeInfo,
+        message: String,
+        attachments: Vec<Attachment>,
+        reply: tokio::sync::oneshot::Sender<Result<String, String>>,
+    },
+    SetMode {
+        session_id: String,
+        mode_id: String,
+        resume: ResumeInfo,
+        reply: tokio::sync::oneshot::Sender<Result<(), String>>,
+    },
+    SetModel {
+        session_id: String,
+        model_id: String,
+        resume: ResumeInfo,
+        reply: tokio::sync::oneshot::Sender<Result<(), String>>,
+    },
+    Cancel {
+        session_id: String,
+        reply: tokio::sync::oneshot::Sender<Result<(), String>>,
+    },
+}
+
+/// What is stored about a persisted session, used when it has to be resumed
+/// in an agent process that doesn't know it yet.
+#[derive(Debug, Clone, Default)]
+pub struct ResumeInfo {
+    pub workspace: Option<String>,
+    /// Mode the session was last in
+    pub mode_id: Option<String>,
+    /// Model the session last used
+    pub model_id: Option<String>,
+}
+
+lazy_static::lazy_static! {
+    /// Running agent processes by worker key: the provider id, or a per-session
+    /// key when sessions are isolated.
+    static ref AGENT_WORKERS: Mutex<HashMap<String, AgentWorker>> = Mutex::new(HashMap::new());
+    /// Which provider each known session id is bound to.
+    static ref SESSION_PROVIDERS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+    /// Which worker hosts each session this process has started or resumed.
+    static ref SESSION_WORKERS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+    /// Held while a worker starts, so that concurrent requests for it wait
+    /// instead of starting a second process.
+    static ref WORKER_STARTS: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_ISOLATED_WORKER: AtomicU64 = AtomicU64::new(1);
+
+/// Returns the provider a session was created with, if this process knows it.
+pub fn session_provider(session_id: &str) -> Option<String> {
+    SESSION_PROVIDERS.lock().unwrap().get(session_id).cloned()
+}
+
+/// Binds a session id to a provider, e.g. when restoring persisted sessions.
+pub fn bind_session(session_id: String, provider_id: String) {
+    SESSION_PROVIDERS
+        .lock()
+        .unwrap()
+        .insert(session_id, provider_id);
+}
+
+/// The worker that hosts `session_id`. Sessions share one process per
+/// provider and launch unless `isolate_sessions` is set.
+fn worker_key(
+    provider: &dyn AgentProvider,
+    session_id: Option<&str>,
+    launch: &AgentLaunch,
+) -> String {
+    if let Some(session_id) = session_id {
+        if let Some(key) = SESSION_WORKERS.lock().unwrap().get(session_id) {
+            return key.clone();
+        }
+        if settings::current().isolate_sessions {
+            return format!("{}#{}", provider.id(), session_id);
+        }
+    }
+    launch.worker_key(provider)
+}
+
+/// Forgets a deleted session and stops its agent process if it had its own.
+pub fn release_session(session_id: &str) {
+    SESSION_PROVIDERS.lock().unwrap().remove(session_id);
+    let Some(key) = SESSION_WORKERS.lock().unwrap().remove(session_id) else {
+        return;
+    };
+    let shared = SESSION_WORKERS
+        .lock()
+        .unwrap()
+        .values()
+        .any(|other| *other == key);
+    if key.contains('#') && !shared {
+        stop_worker(&key);
+    }
+}
+
+/// Cancels the running turn of a session. Pending permission requests are
+/// resolved as cancelled and the session's terminals are killed.
+pub async fn cancel_turn(session_id: String) -> Result<(), String> {
+    let Some(key) = SESSION_WORKERS.lock().unwrap().get(&session_id).cloned() else {
+        // No agent process has seen this session, so nothing is running.
+        return Ok(());
+    };
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let request = WorkerRequest::Cancel {
+        session_id,
+        reply: tx,
+    };
+    if try_send(&key, request).is_some() {
+        // The agent process is gone or still starting, so nothing is running.
+        return Ok(());
+    }
+
+    rx.await
+        .map_err(|err| format!("Agent channel closed: {err}"))?
+}
+
+/// Sends a request to the worker `key`, starting it with `launch` if it isn't
+/// running. Only requests for the same worker wait while it starts.
+async fn send_request(
+    provider: &'static dyn AgentProvider,
+    launch: &AgentLaunch,
+    key: &str,
+    request: WorkerRequest,
+) -> Result<(), String> {
+    let Some(request) = try_send(key, request) else {
+        return Ok(());
+    };
+
+    let starting = WORKER_STARTS
+        .lock()
+        .unwrap()
+        .entry(key.to_string())
+        .or_default()
+        .clone();
+    let _starting = starting.lock().await;
+    // Another request may have started the worker in the meantime.
+    let Some(request) = try_send(key, request) else {
+        return Ok(());
+    };
+
+    // A worker whose supervisor gave up is started again the same way.
+    let launch = AGENT_WORKERS
+        .lock()
+        .unwrap()
+        .get(key)
+        .map(|worker| worker.launch.clone())
+        .unwrap_or_else(|| launch.clone());
+    let worker = start_worker(provider, launch)
+        .await
+        .inspect_err(|err| log::error!("Starting worker {key} failed: {err}"))?;
+    let result = worker
+        .sender
+        .send(request)
+        .map_err(|err| format!("Agent channel send failed: {err}"));
+    AGENT_WORKERS
+        .lock()
+        .unwrap()
+        .insert(key.to_string(), worker);
+    result
+}
+
+/// Hands the request to the running worker `key`. Returns it when there is
+/// none.
+fn try_send(key: &str, request: WorkerRequest) -> Option<WorkerRequest> {
+    let sender = AGENT_WORKERS
+        .lock()
+        .unwrap()
+        .get(key)
+        .map(|worker| worker.sender.clone());
+    match sender {
+        Some(sender) => sender.send(request).err().map(|err| err.0),
+        None => Some(request),
+    }
+}
+
+/// Stops the worker `key`; dropping the sender ends the request loop, which
+/// kills the child.
+fn stop_worker(key: &str) {
+    AGENT_WORKERS.lock().unwrap().remove(key);
+    WORKER_STARTS.lock().unwrap().remove(key);
+}
+
+/// Picks the provider and worker for a session in `workspace` and binds the
+/// session to that worker.
+fn route_session(
+    session_id: Option<&str>,
+    workspace: Option<&str>,
+) -> Result<(&'static dyn AgentProvider, AgentLaunch, String), String> {
+    let provider_id = session_id
+        .and_then(session_provider)
+        .unwrap_or_else(|| DEFAULT_PROVIDER_ID.to_string());
+    let provider = find_provider(&provider_id)?;
+    let launch = AgentLaunch::for_workspace(provider, workspace);
+    let key = worker_key(provider, session_id, &launch);
+    if let Some(session_id) = session_id {
+        SESSION_WORKERS
+            .lock()
+            .unwrap()
+            .insert(session_id.to_string(), key.clone());
+    }
+    Ok((provider, launch, key))
+}
+
+/// Sends a prompt to the agent bound to `session_id` and returns the
+/// accumulated text response. `resume` is only needed for sessions that
+/// were persisted by an earlier run and have to be resumed first.
+pub async fn send_message(
+    message: String,
+    session_id: Option<String>,
+    resume: ResumeInfo,
+    attachments: Vec<Attachment>,
+) -> Result<String, String> {
+    if message.trim().is_empty() && attachments.is_empty() {
+        return Err("Message cannot be empty".into());
+    }
+
+    let (provider, launch, key) =
+        route_session(session_id.as_deref(), resume.workspace.as_deref())?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    send_request(
+        provider,
+        &launch,
+        &key,
+        WorkerRequest::Prompt {
+            session_id,
+            resume,
+            message,
+            attachments,
+            reply: tx,
+        },
+    )
+    .await?;
+
+    rx.await
+        .map_err(|err| format!("Agent channel closed: {err}"))?
+}
+
+/// Switches a session to one of the modes its agent offers.
+pub async fn set_session_mode(
+    session_id: String,
+    mode_id: String,
+    resume: ResumeInfo,
+) -> Result<(), String> {
+    let (provider, launch, key) = route_session(Some(&session_id), resume.workspace.as_deref())?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    send_request(
+        provider,
+        &launch,
+        &key,
+        WorkerRequest::SetMode {
+            session_id,
+            mode_id,
+            resume,
+            reply: tx,
+        },
+    )
+    .await?;
+
+    rx.await
+        .map_err(|err| format!("Agent channel closed: {err}"))?
+}
+
+/// Switches a session to one of the models its agent offers.
+pub async fn set_session_model(
+    session_id: String,
+    model_id: String,
+    resume: ResumeInfo,
+) -> Result<(), String> {
+    let (provider, launch, key) = route_session(Some(&session_id), resume.workspace.as_deref())?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    send_request(
+        provider,
+        &launch,
+        &key,
+        WorkerRequest::SetModel {
+            session_id,
+            model_id,
+            resume,
+            reply: tx,
+        },
+    )
+    .await?;
+
+    rx.await
+        .map_err(|err| format!("Agent channel closed: {err}"))?
+}
+
+/// Starts a new session with the given provider and workspace directory.
+/// If workspace is None, uses the current working directory. The provider,
+/// model and mode default to the workspace configuration; the model then
+/// falls back to the provider's default model from the settings, if any.
+/// An invalid workspace configuration fails the session, and its privileged
+/// parts are left out until the user approved them.
+pub async fn new_session(
+    provider_id: Option<String>,
+    workspace: Option<String>,
+    model_id: Option<String>,
+) -> Result<String, String> {
+    let config = match &workspace {
+        Some(workspace) => {
+            let workspace = Path::new(workspace);
+            workspace_config::request_approval(workspace, &workspace_config::load(workspace)?);
+            workspace_config::load_approved(workspace)?
+        }
+        None => WorkspaceConfig::default(),
+    };
+    let provider = find_provider(
+        provider_id
+            .as_deref()
+            .or(config.agent.provider.as_deref())
+            .unwrap_or(DEFAULT_PROVIDER_ID),
+    )?;
+    let launch = AgentLaunch::new(provider, &config);
+    let key = if settings::current().isolate_sessions {
+        format!(
+            "{}#{}",
+            provider.id(),
+            NEXT_ISOLATED_WORKER.fetch_add(1, Ordering::Relaxed)
+        )
+    } else {
+        launch.worker_key(provider)
+    };
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    send_request(
+        provider,
+        &launch,
+        &key,
+        WorkerRequest::NewSession {
+            workspace,
+            model_id: model_id.or(config.agent.model),
+            mode_id: config.agent.mode,
+            reply: tx,
+        },
+    )
+    .await?;
+
+    let session_id = match rx.await {
+        Ok(Ok(session_id)) => session_id,
+        result => {
+            if key.contains('#') {
+                // Stop the process that was started for the failed session.
+                stop_worker(&key);
+            }
+            return result.map_err(|err| format!("Agent channel closed: {err}"))?;
+        }
+    };
+    bind_session(session_id.clone(), provider.id().to_string());
+    SESSION_WORKERS
+        .lock()
+        .unwrap()
+        .insert(session_id.clone(), key);
+    Ok(session_id)
+}
+
+/// Prompt turns that are running or queued, per session id.
+#[derive(Default)]
+struct TurnTracker {
+    /// Turns of one session run one at a time; different sessions run concurrently.
+    locks: RefCell<HashMap<String, Rc<tokio::sync::Mutex<()>>>>,
+    active: RefCell<HashMap<String, usize>>,
+    cancelled: RefCell<HashSet<String>>,
+}
+
+impl TurnTracker {
+    fn begin(&self, session_id: &str) {
+        *self
+            .active
+            .borrow_mut()
+            .entry(session_id.to_string())
+            .or_default() += 1;
+    }
+
+    fn end(&self, session_id: &str) {
+        let mut active = self.active.borrow_mut();
+        if let Some(count) = active.get_mut(session_id) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(session_id);
+                self.cancelled.borrow_mut().remove(session_id);
+                self.locks.borrow_mut().remove(session_id);
+            }
+        }
+    }
+
+    fn session_lock(&self, session_id: &str) -> Rc<tokio::sync::Mutex<()>> {
+        self.locks
+            .borrow_mut()
+            .entry(session_id.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Flags the session's turns as cancelled. Returns false if none is active.
+    fn request_cancel(&self, session_id: &str) -> bool {
+        if !self.active.borrow().contains_key(session_id) {
+            return false;
+        }
+        self.cancelled.borrow_mut().insert(session_id.to_string());
+        true
+    }
+
+    fn is_cancelled(&self, session_id: &str) -> bool {
+        self.cancelled.borrow().contains(session_id)
+    }
+}
+
+/// Where the prompts of a UI session go. The agent session differs from the
+/// UI session id when a persisted session could not be loaded.
+#[derive(Clone)]
+struct SessionRoute {
+    agent_session: acp::SessionId,
+    workspace: PathBuf,
+}
+
+/// State shared by the tasks of one agent process.
+struct WorkerContext {
+    provider: &'static dyn AgentProvider,
+    agent_conn: acp::ClientSideConnection,
+    client: Arc<AcpClient>,
+    turns: TurnTracker,
+    /// Sessions this agent process knows, keyed by UI session id.
+    sessions: RefCell<HashMap<String, SessionRoute>>,
+    /// Session used for prompts that don't name one
+    default_session: RefCell<Option<String>>,
+    /// Sessions of a crashed predecessor process that are not reattached yet
+    detached: RefCell<HashMap<String, SessionRoute>>,
+    load_session_supported: bool,
+    prompt_capabilities: acp::PromptCapabilities,
+    mcp_capabilities: acp::McpCapabilities,
+    cwd: PathBuf,
+    /// Set once the agent process has exited
+    exited: watch::Sender<bool>,
+}
+
+impl WorkerContext {
+    async fn new_session(
+        &self,
+        workspace: Option<String>,
+        model_id: Option<String>,
+        mode_id: Option<String>,
+    ) -> Result<String, String> {
+        let workspace = workspace
+            .map(PathBuf::from)
+            .unwrap_or_else(|| self.cwd.clone());
+
+        // Validate workspace directory exists
+        if !workspace.exists() {
+            return Err(format!(
+                "Workspace directory does not exist: {}",
+                workspace.display()
+            ));
+        }
+
+        let mcp = mcp::for_session(&workspace, &self.mcp_capabilities)?;
+        let session = self
+            .agent_conn
+            .new_session(acp::NewSessionRequest::new(workspace.clone()).mcp_servers(mcp.servers))
+            .await
+            .map_err(|err| format!("new_session failed: {err}"))?;
+        let session_id = session.session_id.0.to_string();
+        report_skipped_mcp_servers(&session_id, mcp.skipped);
+        modes::SESSION_MODES.set(&session_id, session.modes);
+        models::SESSION_MODELS.set(&session_id, session.models);
+
+        self.client
+            .register_session(session_id.clone(), session_id.clone(), workspace.clone())
+            .await;
+        let route = SessionRoute {
+            agent_session: session.session_id,
+            workspace,
+        };
+        self.sessions
+            .borrow_mut()
+            .insert(session_id.clone(), route.clone());
+        *self.default_session.borrow_mut() = Some(session_id.clone());
+
+        let model_id = model_id.or_else(|| {
+            settings::current()
+                .default_models
+                .get(self.provider.id())
+                .cloned()
+        });
+        if let Some(model_id) = model_id {
+            self.restore_model(&session_id, &route, model_id).await;
+        }
+        if let Some(mode_id) = mode_id {
+            self.select_mode(&session_id, &route, mode_id).await;
+        }
+        Ok(session_id)
+    }
+
+    /// Returns the default session, creating it on first use.
+    async fn default_session(&self) -> Result<String, String> {
+        let existing = self.default_session.borrow().clone();
+        match existing {
+            Some(session_id) => Ok(session_id),
+            None => self.new_session(None, None, None).await,
+        }
+    }
+
+    /// Runs a prompt turn; `TurnTracker::begin` must be called first.
+    async fn run_turn(
+        &self,
+        session_id: String,
+        resume: ResumeInfo,
+        message: String,
+        attachments: Vec<Attachment>,
+    ) -> Result<String, String> {
+        let lock = self.turns.session_lock(&session_id);
+        let _guard = lock.lock().await;
+
+        if self.turns.is_cancelled(&session_id) {
+            // Cancelled while waiting for an earlier turn to finish
+            self.turns.end(&session_id);
+            emit_turn_complete(&session_id, "cancelled");
+            return Err("Turn cancelled".into());
+        }
+
+        let (attachment_blocks, attachments) =
+            match attachments::prepare(attachments, &self.prompt_capabilities) {
+                Ok(prepared) => prepared,
+                Err(err) => {
+                    self.turns.end(&session_id);
+                    return Err(err);
+                }
+            };
+
+        let route = match self.ensure_session(&session_id, resume).await {
+            Ok(route) => route,
+            Err(err) => {
+                self.turns.end(&session_id);
+                return Err(err);
+            }
+        };
+        let agent_session_id = route.agent_session.0.to_string();
+
+        event_bus::emit_event(AgentEvent::Status {
+            session_id: session_id.clone(),
+            status: "Thinking...".to_string(),
+        });
+
+        event_bus::emit_event(AgentEvent::UserMessage {
+            session_id: session_id.clone(),
+            content: message.clone(),
+            attachments,
+        });
+
+        if let Err(err) = checkpoints::begin_turn(&session_id, &route.workspace, &message).await {
+            event_bus::emit_event(AgentEvent::Status {
+                session_id: session_id.clone(),
+                status: format!("Failed to create checkpoint: {err}"),
+            });
+        }
+        file_watcher::begin_turn(&session_id);
+        let mut open_turn = OpenTurn {
+            session_id: &session_id,
+            turns: &self.turns,
+            finished: false,
+        };
+
+        let mut prompt = Vec::new();
+        if !message.is_empty() {
+            prompt.push(acp::ContentBlock::Text(acp::TextContent::new(message)));
+        }
+        prompt.extend(attachment_blocks);
+
+        self.client.begin_turn(&agent_session_id).await;
+
+        let result = self
+            .agent_conn
+            .prompt(acp::PromptRequest::new(route.agent_session, prompt))
+            .await
+            .map_err(|err| format!("prompt failed: {err}"));
+
+        // Notifications the agent sent before its response may still be queued.
+        self.client.flush_notifications(&agent_session_id).await;
+        if result.is_ok() && !self.turns.is_cancelled(&session_id) {
+            hooks::run_post_turn(&session_id, &route.workspace).await;
+        }
+
+        event_bus::emit_event(AgentEvent::Status {
+            session_id: session_id.clone(),
+            status: "idle".to_string(),
+        });
+
+        let response_text = self.client.end_turn(&agent_session_id).await;
+        let foreign_files = || file_watcher::foreign_files(&session_id);
+        let checkpoint = match checkpoints::finish_turn(&session_id, foreign_files).await {
+            Ok(checkpoint) => checkpoint,
+            Err(err) => {
+                event_bus::emit_event(AgentEvent::Status {
+                    session_id: session_id.clone(),
+                    status: format!("Failed to record checkpoint: {err}"),
+                });
+                None
+            }
+        };
+        // Taken after the checkpoint so the watcher has caught up with the
+        // turn's last writes.
+        let files = file_watcher::finish_turn(
+            &session_id,
+            checkpoint
+                .as_ref()
+                .map(|checkpoint| checkpoint.line_counts.as_slice()),
+        );
+        event_bus::emit_event(AgentEvent::TurnFilesChanged {
+            session_id: session_id.clone(),
+            files,
+        });
+
+        // Agents may answer a cancelled turn with an error instead of `cancelled`
+        let cancelled = self.turns.is_cancelled(&session_id);
+        open_turn.finished = true;
+        self.turns.end(&session_id);
+        match result {
+            Ok(response) => {
+                let stop_reason = if cancelled {
+                    "cancelled".to_string()
+                } else {
+                    stop_reason_name(response.stop_reason)
+                };
+                emit_turn_complete(&session_id, &stop_reason);
+                Ok(response_text)
+            }
+            Err(_) if cancelled => {
+                emit_turn_complete(&session_id, "cancelled");
+                Ok(response_text)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns the route of a session, resuming it first if it was persisted
+    /// by an earlier run of the app.
+    async fn ensure_session(
+        &self,
+        session_id: &str,
+        resume: ResumeInfo,
+    ) -> Result<SessionRoute, String> {
+        if let Some(route) = self.sessions.borrow().get(session_id) {
+            return Ok(route.clone());
+        }
+
+        let route = self.resume_session(session_id, resume.workspace).await?;
+
+        // Put the session back into the mode and model it last used.
+        if let Some(mode_id) = resume.mode_id {
+            let restore = modes::SESSION_MODES
+                .get(session_id)
+                .is_some_and(|modes| modes.current_mode_id != mode_id && modes.has_mode(&mode_id));
+            if restore {
+                if let Err(err) = self.apply_mode(session_id, &route, mode_id).await {
+                    event_bus::emit_event(AgentEvent::Status {
+                        session_id: session_id.to_string(),
+                        status: format!("Could not restore session mode: {err}"),
+                    });
+                }
+            }
+        }
+        if let Some(model_id) = resume.model_id {
+            self.restore_model(session_id, &route, model_id).await;
+        }
+        Ok(route)
+    }
+
+    /// Switches to `model_id` unless it is already current. Failures only
+    /// produce a status message; the session keeps the agent's model.
+    async fn restore_model(&self, session_id: &str, route: &SessionRoute, model_id: String) {
+        let Some(models) = models::SESSION_MODELS.get(session_id) else {
+            return;
+        };
+        if models.current_model_id == model_id {
+            return;
+        }
+        let result = if models.has_model(&model_id) {
+            self.apply_model(session_id, route, model_id).await
+        } else {
+            Err(format!("{model_id} is not offered by the agent"))
+        };
+        if let Err(err) = result {
+            event_bus::emit_event(AgentEvent::Status {
+                session_id: session_id.to_string(),
+                status: format!("Could not select model: {err}"),
+            });
+        }
+    }
+
+    /// Switches to the mode a workspace configures for new sessions.
+    /// Failures only produce a status message.
+    async fn select_mode(&self, session_id: &str, route: &SessionRoute, mode_id: String) {
+        let Some(modes) = modes::SESSION_MODES.get(session_id) else {
+            return;
+        };
+        if modes.current_mode_id == mode_id {
+            return;
+        }
+        let result = if modes.has_mode(&mode_id) {
+            self.apply_mode(session_id, route, mode_id).await
+        } else {
+            Err(format!("{mode_id} is not offered by the agent"))
+        };
+        if let Err(err) = result {
+            event_bus::emit_event(AgentEvent::Status {
+                session_id: session_id.to_string(),
+                status: format!("Could not select mode: {err}"),
+            });
+        }
+    }
+
+    async fn resume_session(
+        &self,
+        session_id: &str,
+        workspace: Option<String>,
+    ) -> Result<SessionRoute, String> {
+        let detached = self.detached.borrow_mut().remove(session_id);
+        let workspace = workspace
+            .map(PathBuf::from)
+            .or(detached.map(|route| route.workspace))
+            .unwrap_or_else(|| self.cwd.clone());
+        if !workspace.exists() {
+            return Err(format!(
+                "Workspace directory does not exist: {}",
+                workspace.display()
+            ));
+        }
+        let mcp = mcp::for_session(&workspace, &self.mcp_capabilities)?;
+        report_skipped_mcp_servers(session_id, mcp.skipped);
+
+        let fallback_reason = if self.load_session_supported {
+            event_bus::emit_event(AgentEvent::Status {
+                session_id: session_id.to_string(),
+                status: "Restoring session...".to_string(),
+            });
+
+            // The agent replays the conversation as session notifications.
+            self.client
+                .register_session(
+                    session_id.to_string(),
+                    session_id.to_string(),
+                    workspace.clone(),
+                )
+                .await;
+            self.client.set_replay(session_id, Replay::History).await;
+            let loaded = self
+                .agent_conn
+                .load_session(
+                    acp::LoadSessionRequest::new(
+                        acp::SessionId::new(session_id),
+                        workspace.clone(),
+                    )
+                    .mcp_servers(mcp.servers.clone()),
+                )
+                .await;
+            self.client.set_replay(session_id, Replay::Off).await;
+
+            match loaded {
+                Ok(loaded) => {
+                    modes::SESSION_MODES.set(session_id, loaded.modes);
+                    models::SESSION_MODELS.set(session_id, loaded.models);
+                    let route = SessionRoute {
+                        agent_session: acp::SessionId::new(session_id),
+                        workspace,
+                    };
+                    self.sessions
+                        .borrow_mut()
+                        .insert(session_id.to_string(), route.clone());
+                    event_bus::emit_event(AgentEvent::SessionResumed {
+                        session_id: session_id.to_string(),
+                        agent_session_id: session_id.to_string(),
+                        restored: true,
+                        reason: None,
+                    });
+                    return Ok(route);
+                }
+                Err(err) => {
+                    self.client.forget_session(session_id).await;
+                    format!("load_session failed: {err}")
+                }
+            }
+        } else {
+            "agent does not support loading sessions".to_string()
+        };
+
+        let new_session = self
+            .agent_conn
+            .new_session(acp::NewSessionRequest::new(workspace.clone()).mcp_servers(mcp.servers))
+            .await
+            .map_err(|err| format!("new_session failed: {err}"))?;
+        modes::SESSION_MODES.set(session_id, new_session.modes);
+        models::SESSION_MODELS.set(session_id, new_session.models);
+        let route = SessionRoute {
+            agent_session: new_session.session_id,
+            workspace,
+        };
+        self.client
+            .register_session(
+                route.agent_session.0.to_string(),
+                session_id.to_string(),
+                route.workspace.clone(),
+            )
+            .await;
+        self.sessions
+            .borrow_mut()
+            .insert(session_id.to_string(), route.clone());
+
+        event_bus::emit_event(AgentEvent::SessionResumed {
+            session_id: session_id.to_string(),
+            agent_session_id: route.agent_session.0.to_string(),
+            restored: false,
+            reason: Some(fallback_reason),
+        });
+        event_bus::emit_event(AgentEvent::Status {
+            session_id: session_id.to_string(),
+            status: "New agent session: earlier history is not available to the agent".to_string(),
+        });
+        Ok(route)
+    }
+
+    async fn set_mode(
+        &self,
+        session_id: String,
+        mode_id: String,
+        resume: ResumeInfo,
+    ) -> Result<(), String> {
+        // The requested mode replaces the stored one.
+        let resume = ResumeInfo {
+            mode_id: None,
+            ..resume
+        };
+        let route = self.route(&session_id, resume).await?;
+
+        match modes::SESSION_MODES.get(&session_id) {
+            Some(modes) if modes.has_mode(&mode_id) => {}
+            Some(_) => return Err(format!("Unknown session mode: {mode_id}")),
+            None => return Err("The agent does not support session modes".into()),
+        }
+        self.apply_mode(&session_id, &route, mode_id).await
+    }
+
+    async fn set_model(
+        &self,
+        session_id: String,
+        model_id: String,
+        resume: ResumeInfo,
+    ) -> Result<(), String> {
+        // The requested model replaces the stored one.
+        let resume = ResumeInfo {
+            model_id: None,
+            ..resume
+        };
+        let route = self.route(&session_id, resume).await?;
+
+        match models::SESSION_MODELS.get(&session_id) {
+            Some(models) if models.has_model(&model_id) => {}
+            Some(_) => return Err(format!("Unknown model: {model_id}")),
+            None => return Err("The agent does not support model selection".into()),
+        }
+        self.apply_model(&session_id, &route, model_id).await
+    }
+
+    async fn apply_model(
+        &self,
+        session_id: &str,
+        route: &SessionRoute,
+        model_id: String,
+    ) -> Result<(), String> {
+        self.agent_conn
+            .set_session_model(acp::SetSessionModelRequest::new(
+                route.agent_session.clone(),
+                model_id.clone(),
+            ))
+            .await
+            .map_err(|err| format!("set_session_model failed: {err}"))?;
+
+        models::SESSION_MODELS.set_current(session_id, &model_id);
+        event_bus::emit_event(AgentEvent::ModelChanged {
+            session_id: session_id.to_string(),
+            model_id,
+        });
+        Ok(())
+    }
+
+    /// Like `ensure_session`, for requests outside a turn. Waits for a turn
+    /// that is resuming the session.
+    async fn route(&self, session_id: &str, resume: ResumeInfo) -> Result<SessionRoute, String> {
+        let known = self.sessions.borrow().get(session_id).cloned();
+        if let Some(route) = known {
+            return Ok(route);
+        }
+        let lock = self.turns.session_lock(session_id);
+        let _guard = lock.lock().await;
+        self.ensure_session(session_id, resume).await
+    }
+
+    async fn apply_mode(
+        &self,
+        session_id: &str,
+        route: &SessionRoute,
+        mode_id: String,
+    ) -> Result<(), String> {
+        self.agent_conn
+            .set_session_mode(acp::SetSessionModeRequest::new(
+                route.agent_session.clone(),
+                mode_id.clone(),
+            ))
+            .await
+            .map_err(|err| format!("set_session_mode failed: {err}"))?;
+
+        modes::SESSION_MODES.set_current(session_id, &mode_id);
+        event_bus::emit_event(AgentEvent::ModeChanged {
+            session_id: session_id.to_string(),
+            mode_id,
+        });
+        Ok(())
+    }
+
+    async fn cancel(&self, session_id: String) -> Result<(), String> {
+        if !self.turns.request_cancel(&session_id) {
+            return Ok(());
+        }
+
+        event_bus::emit_event(AgentEvent::Status {
+            session_id: session_id.clone(),
+            status: "Cancelling...".to_string(),
+        });
+        permission::cancel_session(&session_id);
+        self.client.kill_session_terminals(&session_id);
+
+        let agent_session = self
+            .sessions
+            .borrow()
+            .get(&session_id)
+            .map(|route| route.agent_session.clone())
+            .unwrap_or_else(|| acp::SessionId::new(session_id));
+        self.agent_conn
+            .cancel(acp::CancelNotification::new(agent_session))
+            .await
+            .map_err(|err| format!("cancel failed: {err}"))
+    }
+
+    /// Loads the sessions of a crashed predecessor into this agent process.
+    /// Sessions that can't be loaded are resumed on their next prompt.
+    async fn reattach(&self) -> Vec<String> {
+        if !self.load_session_supported {
+            return Vec::new();
+        }
+
+        let detached: Vec<(String, SessionRoute)> =
+            self.detached.borrow().clone().into_iter().collect();
+        let mut reattached = Vec::new();
+        for (session_id, route) in detached {
+            // A workspace whose MCP configuration became invalid is resumed,
+            // and reports the error, on its next prompt.
+            let Ok(mcp) = mcp::for_session(&route.workspace, &self.mcp_capabilities) else {
+                continue;
+            };
+            let agent_session_id = route.agent_session.0.to_string();
+            self.client
+                .register_session(
+                    agent_session_id.clone(),
+                    session_id.clone(),
+                    route.workspace.clone(),
+                )
+                .await;
+            // The UI still shows the conversation, so the replay is dropped.
+            self.client
+                .set_replay(&agent_session_id, Replay::Discard)
+                .await;
+            let loaded = self
+                .agent_conn
+                .load_session(
+                    acp::LoadSessionRequest::new(
+                        route.agent_session.clone(),
+                        route.workspace.clone(),
+                    )
+                    .mcp_servers(mcp.servers),
+                )
+                .await;
+            self.client.set_replay(&agent_session_id, Replay::Off).await;
+
+            if let Ok(loaded) = loaded {
+                modes::SESSION_MODES.set(&session_id, loaded.modes);
+                models::SESSION_MODELS.set(&session_id, loaded.models);
+                self.detached.borrow_mut().remove(&session_id);
+                self.sessions.borrow_mut().insert(session_id.clone(), route);
+                reattached.push(session_id);
+            } else {
+                self.client.forget_session(&agent_session_id).await;
+            }
+        }
+        reattached
+    }
+
+    /// Every session hosted by this process, attached or not.
+    fn all_sessions(&self) -> HashMap<String, SessionRoute> {
+        let mut sessions = self.detached.borrow().clone();
+        sessions.extend(self.sessions.borrow().clone());
+        sessions
+    }
+}
+
+/// Cleans up a turn whose future is dropped before it finished, which
+/// happens when the agent process exits mid-turn, so that the workspace's
+/// checkpoints can be restored again.
+struct OpenTurn<'a> {
+    session_id: &'a str,
+    turns: &'a TurnTracker,
+    finished: bool,
+}
+
+impl Drop for OpenTurn<'_> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        checkpoints::abandon_turn(self.session_id);
+        file_watcher::finish_turn(self.session_id, None);
+        self.turns.end(self.session_id);
+        event_bus::emit_event(AgentEvent::Status {
+            session_id: self.session_id.to_string(),
+            status: "Turn aborted: the agent process exited".to_string(),
+        });
+        emit_turn_complete(self.session_id, "error");
+    }
+}
+
+/// Runs a request in its own task so a long turn in one session doesn't hold
+/// up the others. The request fails if the agent process exits first.
+fn spawn_request<T: 'static>(
+    ctx: &WorkerContext,
+    reply: oneshot::Sender<Result<T, String>>,
+    request: impl Future<Output = Result<T, String>> + 'static,
+) {
+    let mut exited = ctx.exited.subscribe();
+    tokio::task::spawn_local(async move {
+        let result = tokio::select! {
+            result = request => result,
+            _ = exited.wait_for(|exited| *exited) => Err("Agent process exited".to_string()),
+        };
+        let _ = reply.send(result);
+    });
+}
+
+fn stop_reason_name(stop_reason: acp::StopReason) -> String {
+    serde_json::to_value(stop_reason)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_else(|| "end_turn".to_string())
+}
+
+fn report_skipped_mcp_servers(session_id: &str, skipped: Vec<String>) {
+    for status in skipped {
+        event_bus::emit_event(AgentEvent::Status {
+            session_id: session_id.to_string(),
+            status,
+        });
+    }
+}
+
+fn emit_turn_complete(session_id: &str, stop_reason: &str) {
+    turn_state::finish(session_id, stop_reason);
+    event_bus::emit_event(AgentEvent::TurnComplete {
+        session_id: session_id.to_string(),
+        stop_reason: stop_reason.to_string(),
+        model_id: models::SESSION_MODELS.current(session_id),
+    });
+}
+
+/// Consecutive failed starts after which the supervisor gives up. The next
+/// request then starts a fresh worker.
+const MAX_RESTARTS: u32 = 5;
+/// A process that ran this long resets the restart backoff.
+const STABLE_UPTIME: Duration = Duration::from_secs(60);
+const STDERR_TAIL_LINES: usize = 40;
+
+fn restart_delay(attempt: u32) -> Duration {
+    Duration::from_secs((1u64 << attempt.saturating_sub(1).min(5)).min(30))
+}
+
+/// Starts the agent on a thread of its own and waits until it is initialized.
+async fn start_worker(
+    provider: &'static dyn AgentProvider,
+    launch: AgentLaunch,
+) -> Result<AgentWorker, String> {
+    let agent_path = provider.binary_path()?;
+
+    let (ready_tx, ready_rx) = oneshot::channel();
+    let task_launch = launch.clone();
+
+    thread::spawn(move || {
+        let runtime = match Builder::new_current_thread().enable_all().build() {
+            Ok(rt) => rt,
+            Err(err) => {
+                let _ = ready_tx.send(Err(format!("Failed to build runtime: {err}")));
+                return;
+            }
+        };
+
+        let local = LocalSet::new();
+        local.block_on(
+            &runtime,
+            supervise(provider, agent_path, task_launch, ready_tx),
+        );
+    });
+
+    match ready_rx.await {
+        Ok(Ok(sender)) => Ok(AgentWorker { sender, launch }),
+        Ok(Err(err)) => Err(err),
+        Err(_) => Err("Agent worker failed to start".into()),
+    }
+}
+
+/// How an agent process ended
+struct AgentExit {
+    exit_code: Option<i32>,
+    signal: Option<i32>,
+    stderr_tail: Vec<String>,
+    sessions: HashMap<String, SessionRoute>,
+    default_session: Option<String>,
+}
+
+/// Keeps an agent process running: restarts it with backoff when it exits
+/// and reattaches the sessions it hosted. Requests queue up while it restarts.
+async fn supervise(
+    provider: &'static dyn AgentProvider,
+    agent_path: PathBuf,
+    launch: AgentLaunch,
+    ready_tx: oneshot::Sender<Result<mpsc::UnboundedSender<WorkerRequest>, String>>,
+) {
+    let (tx, mut rx) = mpsc::unbounded_channel::<WorkerRequest>();
+    let mut ready_tx = Some(ready_tx);
+    let mut previous: Option<AgentExit> = None;
+    let mut failures = 0;
+
+    loop {
+        let started = Instant::now();
+        let exit = match start_agent(provider, &agent_path, &launch).await {
+            Ok((ctx, child, stderr)) => {
+                if let Some(ready_tx) = ready_tx.take() {
+                    let _ = ready_tx.send(Ok(tx.clone()));
+                }
+                if let Some(previous) = previous.take() {
+                    let session_ids: Vec<String> = previous.sessions.keys().cloned().collect();
+                    *ctx.detached.borrow_mut() = previous.sessions;
+                    *ctx.default_session.borrow_mut() = previous.default_session;
+                    let reattached = ctx.reattach().await;
+                    event_bus::emit_event(AgentEvent::AgentRestarted {
+                        provider_id: provider.id().to_string(),
+                        session_ids,
+                        reattached,
+                    });
+                }
+
+                let Some(exit) = serve(&ctx, child, stderr, &mut rx).await else {
+                    // Every sender is gone: the worker was dropped.
+                    return;
+                };
+                exit
+            }
+            Err(err) => {
+                if let Some(ready_tx) = ready_tx.take() {
+                    let _ = ready_tx.send(Err(err));
+                    return;
+                }
+                log::warn!("Restarting {} failed: {err}", provider.display_name());
+                failures += 1;
+                if failures >= MAX_RESTARTS {
+                    let sessions = previous.map(|exit| exit.sessions).unwrap_or_default();
+                    event_bus::emit_event(AgentEvent::AgentExited {
+                        provider_id: provider.id().to_string(),
+                        session_ids: sessions.keys().cloned().collect(),
+                        exit_code: None,
+                        signal: None,
+                        stderr_tail: vec![err],
+                        restart_in_secs: None,
+                    });
+                    for session_id in sessions.keys() {
+                        event_bus::emit_event(AgentEvent::Status {
+                            session_id: session_id.clone(),
+                            status: format!(
+                                "{} failed to restart; giving up",
+                                provider.display_name()
+                            ),
+                        });
+                    }
+                    return;
+                }
+                sleep(restart_delay(failures)).await;
+                continue;
+            }
+        };
+
+        if started.elapsed() >= STABLE_UPTIME {
+            failures = 0;
+        }
+        failures += 1;
+        let restart_in = (failures < MAX_RESTARTS).then(|| restart_delay(failures));
+
+        event_bus::emit_event(AgentEvent::AgentExited {
+            provider_id: provider.id().to_string(),
+            session_ids: exit.sessions.keys().cloned().collect(),
+            exit_code: exit.exit_code,
+            signal: exit.signal,
+            stderr_tail: exit.stderr_tail.clone(),
+            restart_in_secs: restart_in.map(|delay| delay.as_secs()),
+        });
+        for session_id in exit.sessions.keys() {
+            event_bus::emit_event(AgentEvent::Status {
+                session_id: session_id.clone(),
+                status: match restart_in {
+                    Some(delay) => format!(
+                        "{} exited; restarting in {}s",
+                        provider.display_name(),
+                        delay.as_secs()
+                    ),
+                    None => format!("{} keeps exiting; giving up", provider.display_name()),
+                },
+            });
+        }
+
+        let Some(delay) = restart_in else {
+            return;
+        };
+        previous = Some(exit);
+        sleep(delay).await;
+    }
+}
+
+/// Spawns the agent process and initializes the ACP connection.
+async fn start_agent(
+    provider: &'static dyn AgentProvider,
+    agent_path: &Path,
+    launch: &AgentLaunch,
+) -> Result<(Rc<WorkerContext>, Child, JoinHandle<Vec<String>>), String> {
+    let cwd = std::env::current_dir()
+        .and_then(|path| path.canonicalize())
+        .map_err(|err| format!("Failed to resolve current directory: {err}"))?;
+
+    let mut command = Command::new(agent_path);
+    command
+        .args(provider.args())
+        .args(&launch.args)
+        .envs(provider.env())
+        .envs(&launch.env);
+    if let Some(path) = child_path_env(agent_path) {
+        command.env("PATH", path);
+    }
+
+    let mut child = command
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| format!("Failed to start {}: {err}", provider.display_name()))?;
+
+    let client_arc = Arc::new(AcpClient::new());
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| "Failed to capture agent stdout".to_string())?;
+    let stdout = client_arc.notifications().reader(stdout).compat();
+    let stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| "Failed to capture agent stdin".to_string())?
+        .compat_write();
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| "Failed to capture agent stderr".to_string())?;
+    let stderr = tokio::task::spawn_local(collect_stderr(provider.id(), stderr));
+
+    let (agent_conn, io_task) =
+        acp::ClientSideConnection::new(client_arc.clone(), stdin, stdout, |fut| {
+            tokio::task::spawn_local(fut);
+        });
+    tokio::task::spawn_local(io_task);
+
+    let initialize = agent_conn.initialize(
+        acp::InitializeRequest::new(acp::ProtocolVersion::LATEST)
+            .client_capabilities(
+                acp::ClientCapabilities::new()
+                    .fs(acp::FileSystemCapability::new()
+                        .read_text_file(true)
+                        .write_text_file(true))
+                    .terminal(true),
+            )
+            .client_info(
+                acp::Implementation::new("open-cowork", env!("CARGO_PKG_VERSION"))
+                    .title("Open Cowork Client"),
+            ),
+    );
+    // The connection doesn't notice when the process dies during the handshake.
+    let init = tokio::select! {
+        init = initialize => init.map_err(|err| format!("initialize failed: {err}"))?,
+        status = child.wait() => {
+            let stderr_tail = stderr.await.unwrap_or_default();
+            return Err(format!(
+                "{} exited during initialize ({}): {}",
+                provider.display_name(),
+                status.map(|status| status.to_string()).unwrap_or_else(|err| err.to_string()),
+                stderr_tail.join("\n")
+            ));
+        }
+    };
+
+    let ctx = Rc::new(WorkerContext {
+        provider,
+        agent_conn,
+        client: client_arc,
+        turns: TurnTracker::default(),
+        sessions: RefCell::new(HashMap::new()),
+        default_session: RefCell::new(None),
+        detached: RefCell::new(HashMap::new()),
+        load_session_supported: init.agent_capabilities.load_session,
+        prompt_capabilities: init.agent_capabilities.prompt_capabilities,
+        mcp_capabilities: init.agent_capabilities.mcp_capabilities,
+        cwd,
+        exited: watch::channel(false).0,
+    });
+    Ok((ctx, child, stderr))
+}
+
+/// Handles requests until the agent process exits. Returns `None` once all
+/// senders are dropped, after killing the process.
+async fn serve(
+    ctx: &Rc<WorkerContext>,
+    mut child: Child,
+    stderr: JoinHandle<Vec<String>>,
+    rx: &mut mpsc::UnboundedReceiver<WorkerRequest>,
+) -> Option<AgentExit> {
+    let status = loop {
+        let request = tokio::select! {
+            request = rx.recv() => request,
+            status = child.wait() => break status,
+        };
+        let Some(request) = request else {
+            let _ = child.kill().await;
+            return None;
+        };
+
+        match request {
+            WorkerRequest::NewSession {
+                workspace,
+                model_id,
+                mode_id,
+                reply,
+            } => {
+                let ctx_task = ctx.clone();
+                spawn_request(ctx, reply, async move {
+                    ctx_task.new_session(workspace, model_id, mode_id).await
+                });
+            }
+            WorkerRequest::Prompt {
+                session_id,
+                resume,
+                message,
+                attachments,
+                reply,
+            } => {
+                // Register the turn before spawning so a `Cancel` that
+                // arrives right after it still finds it.
+                if let Some(session_id) = &session_id {
+                    ctx.turns.begin(session_id);
+                }
+                let ctx_task = ctx.clone();
+                spawn_request(ctx, reply, async move {
+                    let session_id = match session_id {
+                        Some(session_id) => session_id,
+                        None => {
+                            let session_id = ctx_task.default_session().await?;
+                            ctx_task.turns.begin(&session_id);
+                            session_id
+                        }
+                    };
+                    ctx_task
+                        .run_turn(session_id, resume, message, attachments)
+                        .await
+                });
+            }
+            WorkerRequest::SetMode {
+                session_id,
+                mode_id,
+                resume,
+                reply,
+            } => {
+                let ctx_task = ctx.clone();
+                spawn_request(ctx, reply, async move {
+                    ctx_task.set_mode(session_id, mode_id, resume).await
+                });
+            }
+            WorkerRequest::SetModel {
+                session_id,
+                model_id,
+                resume,
+                reply,
+            } => {
+                let ctx_task = ctx.clone();
+                spawn_request(ctx, reply, async move {
+                    ctx_task.set_model(session_id, model_id, resume).await
+                });
+            }
+            WorkerRequest::Cancel { session_id, reply } => {
+                let _ = reply.send(ctx.cancel(session_id).await);
+            }
+        }
+    };
+
+    // Fail the requests still waiting on the dead process.
+    ctx.exited.send_replace(true);
+    let sessions = ctx.all_sessions();
+    for session_id in sessions.keys() {
+        permission::cancel_session(session_id);
+        ctx.client.kill_session_terminals(session_id);
+    }
+
+    // stderr reaches EOF once the process is gone, unless a grandchild holds it open.
+    let stderr_tail = tokio::time::timeout(Duration::from_millis(500), stderr)
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .unwrap_or_default();
+    let (exit_code, signal) = match status {
+        Ok(status) => (status.code(), exit_signal(&status)),
+        Err(_) => (None, None),
+    };
+
+    Some(AgentExit {
+        exit_code,
+        signal,
+        stderr_tail,
+        sessions,
+        default_session: ctx.default_session.borrow().clone(),
+    })
+}
+
+/// Logs the agent's stderr under the provider's id as the log target and
+/// returns its last lines.
+async fn collect_stderr(provider_id: &'static str, stderr: ChildStderr) -> Vec<String> {
+    let mut tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        log::debug!(target: provider_id, "{line}");
+        if tail.len() == STDERR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line);
+    }
+    tail.into()
+}
+
+#[cfg(unix)]
+fn exit_signal(status: &std::process::ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: &std::process::ExitStatus) -> Option<i32> {
+    None
+}
@@ -0,0 +1,115 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use super::path_policy;
+use crate::event_bus::{self, AgentEvent};
+
+/// Unsaved contents of a file open in an editor
+#[derive(Debug, Clone)]
+struct Buffer {
+    content: String,
+    /// Bumped on every change, by the editor or the agent
+    version: u64,
+}
+
+#[derive(Default)]
+struct Registry {
+    buffers: HashMap<PathBuf, Buffer>,
+    /// Buffer versions each session's agent has read, by path
+    seen: HashMap<String, HashMap<PathBuf, u64>>,
+}
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: Mutex<Registry> = Mutex::new(Registry::default());
+}
+
+/// Buffers are keyed by the resolved path, which is what file requests use,
+/// also for files that don't exist on disk yet.
+fn key(path: &Path) -> PathBuf {
+    path_policy::canonicalize_lenient(path)
+}
+
+/// Stores the editor's unsaved contents of `path` and returns the buffer's
+/// new version.
+pub fn update(path: &Path, content: String) -> u64 {
+    let mut registry = REGISTRY.lock().unwrap();
+    let buffer = registry.buffers.entry(key(path)).or_insert(Buffer {
+        content: String::new(),
+        version: 0,
+    });
+    buffer.content = content;
+    buffer.version += 1;
+    buffer.version
+}
+
+/// Drops the buffer, e.g. after the editor saved or closed the file.
+pub fn close(path: &Path) {
+    let key = key(path);
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.buffers.remove(&key);
+    for seen in registry.seen.values_mut() {
+        seen.remove(&key);
+    }
+}
+
+/// Paths that currently have an open buffer
+pub fn list() -> Vec<String> {
+    REGISTRY
+        .lock()
+        .unwrap()
+        .buffers
+        .keys()
+        .map(|path| path.display().to_string())
+        .collect()
+}
+
+/// The buffer of `path` as the session's agent reads it, if one is open.
+pub fn read(session_id: &str, path: &Path) -> Option<String> {
+    let mut registry = REGISTRY.lock().unwrap();
+    let buffer = registry.buffers.get(path)?.clone();
+    registry
+        .seen
+        .entry(session_id.to_string())
+        .or_default()
+        .insert(path.to_path_buf(), buffer.version);
+    Some(buffer.content)
+}
+
+/// Writes into the open buffer of `path` instead of the file. Returns `None`
+/// when no buffer is open, and an error if the buffer was edited since the
+/// agent last read it.
+pub fn write(session_id: &str, path: &Path, content: &str) -> Option<Result<(), String>> {
+    let mut registry = REGISTRY.lock().unwrap();
+    let Registry { buffers, seen } = &mut *registry;
+    let buffer = buffers.get_mut(path)?;
+
+    let seen = seen.entry(session_id.to_string()).or_default();
+    if seen
+        .get(path)
+        .is_some_and(|version| *version != buffer.version)
+    {
+        return Some(Err(format!(
+            "The open editor buffer of {} changed since the agent last read it; read it again before writing",
+            path.display()
+        )));
+    }
+
+    buffer.content = content.to_string();
+    buffer.version += 1;
+    seen.insert(path.to_path_buf(), buffer.version);
+
+    event_bus::emit_event(AgentEvent::BufferChanged {
+        session_id: session_id.to_string(),
+        path: path.display().to_string(),
+        content: buffer.content.clone(),
+        version: buffer.version,
+    });
+    Some(Ok(()))
+}
+
+pub fn forget_session(session_id: &str) {
+    REGISTRY.lock().unwrap().seen.remove(session_id);
+}
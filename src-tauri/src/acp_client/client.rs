@@ -1,62 +1,225 @@
 use std::{
-    fs,
+    collections::HashMap,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
+use super::{
+    buffers, commands,
+    file_access::{self, Access},
+    file_read::{self, LineRange},
+    file_versions, file_write, modes,
+    notifications::NotificationTracker,
+    path_policy, permission,
+    policy::{self, ApprovalPolicy, ApprovalRule, PermissionContext},
+    staging,
+    terminal::TerminalManager,
+    turn_state,
+};
 use crate::event_bus::{self, AgentEvent};
+use crate::{file_watcher, settings, workspace_config};
 use agent_client_protocol as acp;
 use tokio::sync::Mutex;
 
+/// What to do with the conversation history an agent replays from `load_session`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Replay {
+    Off,
+    /// Forward it to the UI as history
+    History,
+    /// Drop it, e.g. when reattaching a session the UI already shows
+    Discard,
+}
+
+/// Client-side state of one agent session.
+struct SessionState {
+    /// The id the UI knows the session by. Differs from the agent session id
+    /// when a persisted session was resumed as a new agent session.
+    ui_session_id: String,
+    workspace: PathBuf,
+    /// Agent text of the running turn
+    output: String,
+    in_turn: bool,
+    replay: Replay,
+}
+
 #[derive(Clone)]
 pub struct AcpClient {
-    output: Arc<Mutex<String>>,
-    workspace: Arc<Mutex<Option<PathBuf>>>,
-    current_session_id: Arc<Mutex<Option<String>>>,
+    /// Keyed by agent session id; notifications are routed by their own session id.
+    sessions: Arc<Mutex<HashMap<String, SessionState>>>,
+    terminals: Arc<TerminalManager>,
+    notifications: NotificationTracker,
 }
 
 impl AcpClient {
-    pub fn new(output: Arc<Mutex<String>>) -> Self {
+    pub fn new() -> Self {
         Self {
-            output,
-            workspace: Arc::new(Mutex::new(None)),
-            current_session_id: Arc::new(Mutex::new(None)),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            terminals: Arc::new(TerminalManager::new()),
+            notifications: NotificationTracker::new(),
         }
     }
 
-    pub async fn set_current_session_id(&self, session_id: Option<String>) {
-        *self.current_session_id.lock().await = session_id;
+    /// Counts the session notifications read from the agent's output, for
+    /// [`Self::flush_notifications`].
+    pub fn notifications(&self) -> &NotificationTracker {
+        &self.notifications
     }
 
-    pub async fn set_workspace(&self, path: PathBuf) {
-        *self.workspace.lock().await = Some(path);
+    /// Waits until the session's notifications that were sent before the
+    /// latest response have been handled.
+    pub async fn flush_notifications(&self, agent_session_id: &str) {
+        self.notifications.flush(agent_session_id).await;
     }
 
-    async fn ensure_in_workspace(&self, file_path: &Path) -> Result<(), String> {
-        let workspace = self.workspace.lock().await;
-        if let Some(ref workspace_path) = *workspace {
-            if let Ok(abs_path) = file_path.canonicalize() {
-                if let Ok(workspace_abs) = workspace_path.canonicalize() {
-                    if !abs_path.starts_with(&workspace_abs) {
-                        return Err(format!(
-                            "Access denied: {} is outside workspace {}",
-                            file_path.display(),
-                            workspace_path.display()
-                        ));
-                    }
-                }
+    /// Registers an agent session before any request or notification for it arrives.
+    pub async fn register_session(
+        &self,
+        agent_session_id: String,
+        ui_session_id: String,
+        workspace: PathBuf,
+    ) {
+        if let Err(err) = file_watcher::watch(&ui_session_id, &workspace) {
+            event_bus::emit_event(AgentEvent::Status {
+                session_id: ui_session_id.clone(),
+                status: err,
+            });
+        }
+        self.sessions.lock().await.insert(
+            agent_session_id,
+            SessionState {
+                ui_session_id,
+                workspace,
+                output: String::new(),
+                in_turn: false,
+                replay: Replay::Off,
+            },
+        );
+    }
+
+    pub async fn forget_session(&self, agent_session_id: &str) {
+        self.notifications.forget(agent_session_id);
+        if let Some(state) = self.sessions.lock().await.remove(agent_session_id) {
+            file_watcher::unwatch(&state.ui_session_id);
+        }
+    }
+
+    /// Sets how notifications are treated while `load_session` replays the history.
+    pub async fn set_replay(&self, agent_session_id: &str, replay: Replay) {
+        if let Some(state) = self.sessions.lock().await.get_mut(agent_session_id) {
+            state.replay = replay;
+        }
+    }
+
+    /// Starts collecting agent text for a new turn.
+    pub async fn begin_turn(&self, agent_session_id: &str) {
+        if let Some(state) = self.sessions.lock().await.get_mut(agent_session_id) {
+            state.output.clear();
+            state.in_turn = true;
+            turn_state::begin(&state.ui_session_id);
+        }
+    }
+
+    /// Ends the turn and returns the agent text collected during it.
+    pub async fn end_turn(&self, agent_session_id: &str) -> String {
+        match self.sessions.lock().await.get_mut(agent_session_id) {
+            Some(state) => {
+                state.in_turn = false;
+                std::mem::take(&mut state.output)
             }
+            None => String::new(),
         }
-        Ok(())
     }
 
-    async fn emit_status(&self, status: String) {
-        if let Some(session_id) = &*self.current_session_id.lock().await {
-            event_bus::emit_event(AgentEvent::Status {
-                session_id: session_id.clone(),
-                status,
-            });
+    pub fn kill_session_terminals(&self, session_id: &str) {
+        self.terminals.kill_session(session_id);
+    }
+
+    /// Maps the session id the agent uses back to the one the UI knows.
+    async fn ui_session_id(&self, agent_session_id: &acp::SessionId) -> acp::SessionId {
+        match self.sessions.lock().await.get(agent_session_id.0.as_ref()) {
+            Some(state) => acp::SessionId::new(state.ui_session_id.as_str()),
+            None => agent_session_id.clone(),
+        }
+    }
+
+    async fn workspace(&self, agent_session_id: &acp::SessionId) -> Option<PathBuf> {
+        self.sessions
+            .lock()
+            .await
+            .get(agent_session_id.0.as_ref())
+            .map(|state| state.workspace.clone())
+    }
+
+    /// Resolves a path the agent asked for against the session's workspace.
+    async fn resolve_path(
+        &self,
+        agent_session_id: &acp::SessionId,
+        path: &Path,
+    ) -> acp::Result<PathBuf> {
+        let workspace = self
+            .workspace(agent_session_id)
+            .await
+            .ok_or_else(|| acp::Error::new(-1, "No workspace for this session"))?;
+        path_policy::resolve(&workspace, path).map_err(|e| acp::Error::new(-1, e.to_string()))
+    }
+
+    /// Resolves a path like [`Self::resolve_path`] and checks it against the
+    /// sensitive-file rules, reporting blocked accesses to the UI.
+    async fn guard_path(
+        &self,
+        agent_session_id: &acp::SessionId,
+        path: &Path,
+        access: Access,
+    ) -> acp::Result<PathBuf> {
+        let resolved = self.resolve_path(agent_session_id, path).await?;
+        let workspace = self
+            .workspace(agent_session_id)
+            .await
+            .and_then(|workspace| workspace.canonicalize().ok())
+            .ok_or_else(|| acp::Error::new(-1, "No workspace for this session"))?;
+
+        if let Err(blocked) = file_access::check(
+            &workspace,
+            &resolved,
+            access,
+            &settings::current().file_access,
+        ) {
+            self.emit_status(agent_session_id, blocked.to_string())
+                .await;
+            return Err(acp::Error::new(-8, blocked.to_string()));
         }
+        Ok(resolved)
+    }
+
+    /// Forwards a message replayed by `load_session` as conversation history.
+    fn replay_history(session_id: &str, update: acp::SessionUpdate) {
+        let (role, content) = match update {
+            acp::SessionUpdate::UserMessageChunk(chunk) => ("user", chunk.content),
+            acp::SessionUpdate::AgentMessageChunk(chunk) => ("agent", chunk.content),
+            acp::SessionUpdate::AgentThoughtChunk(chunk) => ("thought", chunk.content),
+            _ => return,
+        };
+
+        event_bus::emit_event(AgentEvent::HistoryChunk {
+            session_id: session_id.to_string(),
+            role: role.to_string(),
+            content: content_text(content),
+        });
+    }
+
+    async fn emit_status(&self, agent_session_id: &acp::SessionId, status: String) {
+        event_bus::emit_event(AgentEvent::Status {
+            session_id: self.ui_session_id(agent_session_id).await.0.to_string(),
+            status,
+        });
+    }
+}
+
+impl Default for AcpClient {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -64,19 +227,64 @@ impl AcpClient {
 impl acp::Client for AcpClient {
     async fn request_permission(
         &self,
-        args: acp::RequestPermissionRequest,
+        mut args: acp::RequestPermissionRequest,
     ) -> acp::Result<acp::RequestPermissionResponse> {
-        if let Some(option) = args.options.first() {
-            return Ok(acp::RequestPermissionResponse::new(
-                acp::RequestPermissionOutcome::Selected(acp::SelectedPermissionOutcome::new(
-                    option.option_id.clone(),
-                )),
-            ));
+        let workspace = self.workspace(&args.session_id).await;
+        args.session_id = self.ui_session_id(&args.session_id).await;
+        let session_id = args.session_id.0.to_string();
+        let settings = settings::current();
+
+        let (workspace_policy, workspace_approved) = match &workspace {
+            Some(workspace) => match workspace_config::load(workspace) {
+                Ok(config) => {
+                    let approved = workspace_config::is_approved(workspace, &config);
+                    (config.approval, approved)
+                }
+                Err(err) => {
+                    self.emit_status(&args.session_id, err).await;
+                    (ApprovalPolicy::default(), false)
+                }
+            },
+            None => (ApprovalPolicy::default(), false),
+        };
+
+        let ctx = PermissionContext::new(&args.tool_call, workspace.as_deref());
+        let matched = policy::evaluate(
+            &session_id,
+            &workspace_policy,
+            workspace_approved,
+            &settings.approval,
+            &ctx,
+        );
+
+        if let Some(matched) = &matched {
+            if let Some(outcome) =
+                permission::resolve_by_rule(&args, matched.action, matched.rule.clone())
+            {
+                return Ok(acp::RequestPermissionResponse::new(outcome));
+            }
+        }
+
+        self.emit_status(&args.session_id, "Waiting for permission...".to_string())
+            .await;
+        let timeout = Duration::from_secs(settings.permission_timeout_secs);
+        let outcome = permission::ask(&args, timeout, matched.map(|m| m.rule)).await;
+
+        let grant = permission::is_allow_always(&args, &outcome)
+            .then(|| ApprovalRule::grant_for(&ctx, args.tool_call.fields.title.as_deref()))
+            .flatten();
+        if let Some(grant) = grant {
+            policy::add_grant(&session_id, grant.clone());
+            if let Some(workspace) = workspace {
+                event_bus::emit_event(AgentEvent::ApprovalGranted {
+                    session_id,
+                    workspace: workspace.to_string_lossy().to_string(),
+                    rule: grant,
+                });
+            }
         }
 
-        Ok(acp::RequestPermissionResponse::new(
-            acp::RequestPermissionOutcome::Cancelled,
-        ))
+        Ok(acp::RequestPermissionResponse::new(outcome))
     }
 
     async fn write_text_file(
@@ -84,23 +292,38 @@ impl acp::Client for AcpClient {
         args: acp::WriteTextFileRequest,
     ) -> acp::Result<acp::WriteTextFileResponse> {
         let path = PathBuf::from(&args.path);
-        
-        self.emit_status(format!("Writing file: {}", path.display())).await;
 
-        if let Err(e) = self.ensure_in_workspace(&path).await {
-            return Err(acp::Error::new(-1, e));
+        self.emit_status(
+            &args.session_id,
+            format!("Writing file: {}", path.display()),
+        )
+        .await;
+
+        let path = self
+            .guard_path(&args.session_id, &path, Access::Write)
+            .await?;
+
+        let session_id = self.ui_session_id(&args.session_id).await;
+
+        // An open editor buffer takes the write; the user saves it.
+        if let Some(result) = buffers::write(&session_id.0, &path, &args.content) {
+            result.map_err(|reason| edit_conflict(&session_id.0, &path, reason))?;
+            return Ok(acp::WriteTextFileResponse::new());
         }
 
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            if !parent.exists() {
-                fs::create_dir_all(parent)
-                    .map_err(|e| acp::Error::new(-2, format!("Failed to create directory: {}", e)))?;
-            }
+        file_versions::check(&session_id.0, &path)
+            .map_err(|reason| edit_conflict(&session_id.0, &path, reason))?;
+
+        if settings::current().review_writes {
+            staging::stage(&session_id.0, &path, args.content)
+                .map_err(|e| acp::Error::new(-3, e))?;
+            return Ok(acp::WriteTextFileResponse::new());
         }
 
-        fs::write(&path, args.content)
-            .map_err(|e| acp::Error::new(-3, format!("Failed to write file: {}", e)))?;
+        file_watcher::note_agent_write(&session_id.0, &path);
+        file_write::write(&session_id.0, &path, &args.content)
+            .map_err(|e| acp::Error::new(-3, e))?;
+        file_versions::record(&session_id.0, &path);
 
         Ok(acp::WriteTextFileResponse::new())
     }
@@ -110,119 +333,200 @@ impl acp::Client for AcpClient {
         args: acp::ReadTextFileRequest,
     ) -> acp::Result<acp::ReadTextFileResponse> {
         let path = PathBuf::from(&args.path);
-        
-        self.emit_status(format!("Reading file: {}", path.display())).await;
 
-        if let Err(e) = self.ensure_in_workspace(&path).await {
-            return Err(acp::Error::new(-1, e));
+        self.emit_status(
+            &args.session_id,
+            format!("Reading file: {}", path.display()),
+        )
+        .await;
+
+        let path = self
+            .guard_path(&args.session_id, &path, Access::Read)
+            .await?;
+
+        let range = LineRange {
+            line: args.line,
+            limit: args.limit,
+        };
+
+        // Staged writes, then unsaved editor buffers, shadow the file on disk
+        let session_id = self.ui_session_id(&args.session_id).await;
+        if let Some(content) =
+            staging::read(&session_id.0, &path).or_else(|| buffers::read(&session_id.0, &path))
+        {
+            return Ok(acp::ReadTextFileResponse::new(range.select(&content)));
         }
 
-        let content = fs::read_to_string(&path)
-            .map_err(|e| acp::Error::new(-4, format!("Failed to read file: {}", e)))?;
+        let read = file_read::read_hashed(&path, range, settings::current().max_read_bytes)
+            .map_err(|e| acp::Error::new(-4, e))?;
+        file_versions::record_read(&session_id.0, &path, read.hash);
 
-        Ok(acp::ReadTextFileResponse::new(content))
+        Ok(acp::ReadTextFileResponse::new(read.text))
     }
 
     async fn create_terminal(
         &self,
-        _args: acp::CreateTerminalRequest,
+        mut args: acp::CreateTerminalRequest,
     ) -> Result<acp::CreateTerminalResponse, acp::Error> {
-        Err(acp::Error::method_not_found())
+        let cwd = match &args.cwd {
+            Some(cwd) => cwd.clone(),
+            None => self
+                .workspace(&args.session_id)
+                .await
+                .ok_or_else(|| acp::Error::new(-5, "No workspace selected for terminal"))?,
+        };
+
+        self.emit_status(&args.session_id, format!("Running: {}", args.command))
+            .await;
+
+        let cwd = self.resolve_path(&args.session_id, &cwd).await?;
+
+        // The workspace's variables come first so the agent can override them.
+        if let Some(workspace) = self.workspace(&args.session_id).await {
+            match workspace_config::load_approved(&workspace) {
+                Ok(config) => {
+                    let mut env: Vec<acp::EnvVariable> = config
+                        .env
+                        .into_iter()
+                        .map(|(name, value)| acp::EnvVariable::new(name, value))
+                        .collect();
+                    env.append(&mut args.env);
+                    args.env = env;
+                }
+                Err(err) => self.emit_status(&args.session_id, err).await,
+            }
+        }
+
+        args.session_id = self.ui_session_id(&args.session_id).await;
+
+        let terminal_id = self
+            .terminals
+            .create(args, cwd)
+            .map_err(|e| acp::Error::new(-6, e))?;
+
+        Ok(acp::CreateTerminalResponse::new(terminal_id))
     }
 
     async fn terminal_output(
         &self,
-        _args: acp::TerminalOutputRequest,
+        args: acp::TerminalOutputRequest,
     ) -> acp::Result<acp::TerminalOutputResponse> {
-        Err(acp::Error::method_not_found())
+        self.terminals
+            .output(&args.terminal_id)
+            .map_err(|e| acp::Error::new(-7, e))
     }
 
     async fn release_terminal(
         &self,
-        _args: acp::ReleaseTerminalRequest,
+        args: acp::ReleaseTerminalRequest,
     ) -> acp::Result<acp::ReleaseTerminalResponse> {
-        Err(acp::Error::method_not_found())
+        self.terminals
+            .release(&args.terminal_id)
+            .map_err(|e| acp::Error::new(-7, e))?;
+
+        Ok(acp::ReleaseTerminalResponse::new())
     }
 
     async fn wait_for_terminal_exit(
         &self,
-        _args: acp::WaitForTerminalExitRequest,
+        args: acp::WaitForTerminalExitRequest,
     ) -> acp::Result<acp::WaitForTerminalExitResponse> {
-        Err(acp::Error::method_not_found())
+        let exit_status = self
+            .terminals
+            .wait_for_exit(&args.terminal_id)
+            .await
+            .map_err(|e| acp::Error::new(-7, e))?;
+
+        Ok(acp::WaitForTerminalExitResponse::new(exit_status))
     }
 
     async fn kill_terminal_command(
         &self,
-        _args: acp::KillTerminalCommandRequest,
+        args: acp::KillTerminalCommandRequest,
     ) -> acp::Result<acp::KillTerminalCommandResponse> {
-        Err(acp::Error::method_not_found())
+        self.terminals
+            .kill(&args.terminal_id)
+            .map_err(|e| acp::Error::new(-7, e))?;
+
+        Ok(acp::KillTerminalCommandResponse::new())
     }
 
     async fn session_notification(
         &self,
         args: acp::SessionNotification,
     ) -> acp::Result<(), acp::Error> {
-        if let Some(session_id) = &*self.current_session_id.lock().await {
-            event_bus::emit_event(AgentEvent::Update {
-                session_id: session_id.clone(),
-                update: args.update.clone(),
-            });
+        let _handling = self.notifications.handling(&args.session_id.0);
+        let mut sessions = self.sessions.lock().await;
+        let Some(state) = sessions.get_mut(args.session_id.0.as_ref()) else {
+            // Not a session this client created or loaded
+            return Ok(());
+        };
+        if state.replay == Replay::Discard {
+            return Ok(());
+        }
+        let session_id = state.ui_session_id.clone();
+
+        event_bus::emit_event(AgentEvent::Update {
+            session_id: session_id.clone(),
+            update: Box::new(args.update.clone()),
+            replayed: state.replay == Replay::History,
+        });
+
+        if state.replay == Replay::History {
+            Self::replay_history(&session_id, args.update);
+            return Ok(());
         }
 
         match args.update {
             acp::SessionUpdate::AgentMessageChunk(acp::ContentChunk { content, .. }) => {
-                let text = match content {
-                    acp::ContentBlock::Text(text_content) => text_content.text,
-                    acp::ContentBlock::Image(_) => "<image>".into(),
-                    acp::ContentBlock::Audio(_) => "<audio>".into(),
-                    acp::ContentBlock::ResourceLink(resource_link) => resource_link.uri,
-                    acp::ContentBlock::Resource(_) => "<resource>".into(),
-                    _ => "<unknown>".into(),
-                };
-
-                let mut output = self.output.lock().await;
-                if !output.is_empty() {
-                    output.push('\n');
-                    if let Some(session_id) = &*self.current_session_id.lock().await {
+                let text = content_text(content);
+
+                if state.in_turn {
+                    if !state.output.is_empty() {
+                        state.output.push('\n');
                         event_bus::emit_event(AgentEvent::Chunk {
                             session_id: session_id.clone(),
                             content: "\n".to_string(),
                         });
                     }
+                    state.output.push_str(&text);
                 }
-                output.push_str(&text);
 
-                if let Some(session_id) = &*self.current_session_id.lock().await {
-                    event_bus::emit_event(AgentEvent::Chunk {
-                        session_id: session_id.clone(),
-                        content: text,
-                    });
-                }
+                event_bus::emit_event(AgentEvent::Chunk {
+                    session_id,
+                    content: text,
+                });
             }
             acp::SessionUpdate::UserMessageChunk(_) => {}
             acp::SessionUpdate::AgentThoughtChunk(acp::ContentChunk { content, .. }) => {
-                println!("AgentThoughtChunk: {:?}", content);
-                let text = match content {
-                    acp::ContentBlock::Text(text_content) => text_content.text,
-                    acp::ContentBlock::Image(_) => "<image>".into(),
-                    acp::ContentBlock::Audio(_) => "<audio>".into(),
-                    acp::ContentBlock::ResourceLink(resource_link) => resource_link.uri,
-                    acp::ContentBlock::Resource(_) => "<resource>".into(),
-                    _ => "<unknown>".into(),
-                };
-
-                if let Some(session_id) = &*self.current_session_id.lock().await {
-                    event_bus::emit_event(AgentEvent::ThoughtChunk {
-                        session_id: session_id.clone(),
-                        content: text,
-                    });
+                let text = content_text(content);
+
+                event_bus::emit_event(AgentEvent::ThoughtChunk {
+                    session_id,
+                    content: text,
+                });
+            }
+            acp::SessionUpdate::ToolCall(_)
+            | acp::SessionUpdate::ToolCallUpdate(_)
+            | acp::SessionUpdate::Plan(_) => {
+                if let Some(event) = turn_state::apply(&session_id, &args.update) {
+                    event_bus::emit_event(event);
                 }
             }
-            acp::SessionUpdate::ToolCall(_) => {}
-            acp::SessionUpdate::ToolCallUpdate(_) => {}
-            acp::SessionUpdate::Plan(_) => {}
-            acp::SessionUpdate::AvailableCommandsUpdate(_) => {}
-            acp::SessionUpdate::CurrentModeUpdate(_) => {}
+            acp::SessionUpdate::AvailableCommandsUpdate(update) => {
+                let commands = commands::set(&session_id, update.available_commands);
+                event_bus::emit_event(AgentEvent::CommandsAvailable {
+                    session_id,
+                    commands,
+                });
+            }
+            acp::SessionUpdate::CurrentModeUpdate(update) => {
+                modes::SESSION_MODES.set_current(&session_id, &update.current_mode_id.0);
+                event_bus::emit_event(AgentEvent::ModeChanged {
+                    session_id,
+                    mode_id: update.current_mode_id.0.to_string(),
+                });
+            }
             _ => {}
         }
         Ok(())
@@ -236,3 +540,24 @@ impl acp::Client for AcpClient {
         Err(acp::Error::method_not_found())
     }
 }
+
+/// Reports a write that would overwrite changes the agent has not seen.
+fn edit_conflict(session_id: &str, path: &Path, reason: String) -> acp::Error {
+    event_bus::emit_event(AgentEvent::EditConflict {
+        session_id: session_id.to_string(),
+        path: path.display().to_string(),
+        reason: reason.clone(),
+    });
+    acp::Error::new(-9, reason)
+}
+
+pub(crate) fn content_text(content: acp::ContentBlock) -> String {
+    match content {
+        acp::ContentBlock::Text(text_content) => text_content.text,
+        acp::ContentBlock::Image(_) => "<image>".into(),
+        acp::ContentBlock::Audio(_) => "<audio>".into(),
+        acp::ContentBlock::ResourceLink(resource_link) => resource_link.uri,
+        acp::ContentBlock::Resource(_) => "<resource>".into(),
+        _ => "<unknown>".into(),
+    }
+}
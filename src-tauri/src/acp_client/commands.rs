@@ -0,0 +1,95 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use agent_client_protocol as acp;
+use serde::{Deserialize, Serialize};
+
+/// A slash command an agent advertised for a session
+#[derive(Debug, Clone, Serialize)]
+pub struct SlashCommand {
+    pub name: String,
+    pub description: String,
+    /// Shown while the input is empty; `None` if the command takes no input
+    pub input_hint: Option<String>,
+}
+
+/// A slash command the user picked, sent instead of a free-form message
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommandInvocation {
+    pub name: String,
+    #[serde(default)]
+    pub input: Option<String>,
+}
+
+impl From<acp::AvailableCommand> for SlashCommand {
+    fn from(command: acp::AvailableCommand) -> Self {
+        let input_hint = command.input.map(|input| match input {
+            acp::AvailableCommandInput::Unstructured(input) => input.hint,
+            // Unknown input kinds still take the rest of the line.
+            _ => String::new(),
+        });
+        Self {
+            name: command.name,
+            description: command.description,
+            input_hint,
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Latest `available_commands_update` per UI session id
+    static ref SESSION_COMMANDS: Mutex<HashMap<String, Vec<SlashCommand>>> = Mutex::new(HashMap::new());
+}
+
+/// Replaces the session's commands; every update carries the full list.
+pub fn set(session_id: &str, commands: Vec<acp::AvailableCommand>) -> Vec<SlashCommand> {
+    let commands: Vec<SlashCommand> = commands.into_iter().map(SlashCommand::from).collect();
+    SESSION_COMMANDS
+        .lock()
+        .unwrap()
+        .insert(session_id.to_string(), commands.clone());
+    commands
+}
+
+/// Commands whose name starts with `prefix`, for autocomplete. A leading `/`
+/// in the prefix is ignored.
+pub fn list(session_id: &str, prefix: Option<&str>) -> Vec<SlashCommand> {
+    let prefix = prefix.unwrap_or_default().trim_start_matches('/');
+    SESSION_COMMANDS
+        .lock()
+        .unwrap()
+        .get(session_id)
+        .map(|commands| {
+            commands
+                .iter()
+                .filter(|command| command.name.starts_with(prefix))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn forget(session_id: &str) {
+    SESSION_COMMANDS.lock().unwrap().remove(session_id);
+}
+
+/// Checks an invocation against the commands the agent advertised and
+/// returns the prompt text for it.
+pub fn prompt_text(session_id: &str, invocation: &CommandInvocation) -> Result<String, String> {
+    let name = invocation.name.trim_start_matches('/');
+    let commands = SESSION_COMMANDS.lock().unwrap();
+    let command = commands
+        .get(session_id)
+        .and_then(|commands| commands.iter().find(|command| command.name == name))
+        .ok_or_else(|| format!("The agent does not offer the command /{name}"))?;
+
+    let input = invocation
+        .input
+        .as_deref()
+        .map(str::trim)
+        .filter(|input| !input.is_empty());
+    match (input, &command.input_hint) {
+        (Some(input), Some(_)) => Ok(format!("/{name} {input}")),
+        (Some(_), None) => Err(format!("/{name} does not take any input")),
+        (None, _) => Ok(format!("/{name}")),
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use globset::{GlobBuilder, GlobMatcher};
+use serde::{Deserialize, Serialize};
+
+use crate::workspace_config;
+
+/// Per-workspace deny-list in `.gitignore` syntax, looked up at the workspace root
+pub const DENY_FILE_NAME: &str = ".open-cowork-deny";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// `.gitignore`-style patterns guarding sensitive files. Later patterns win,
+/// and a leading `!` re-allows paths an earlier pattern denied. Workspace
+/// patterns cannot re-allow what these deny.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileAccessRules {
+    /// Paths the agent may neither read nor write
+    pub deny: Vec<String>,
+    /// Paths the agent may read but not write
+    pub deny_write: Vec<String>,
+}
+
+impl Default for FileAccessRules {
+    fn default() -> Self {
+        let patterns = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect();
+        Self {
+            deny: patterns(&[
+                ".env",
+                ".env.*",
+                "!.env.example",
+                "!.env.sample",
+                "id_rsa",
+                "id_dsa",
+                "id_ecdsa",
+                "id_ed25519",
+                "*.pem",
+                "*.key",
+                "*.p12",
+                "*.pfx",
+                ".netrc",
+                ".git-credentials",
+                "/.git/config",
+            ]),
+            deny_write: patterns(&["/.git/", workspace_config::CONFIG_FILE_NAME, DENY_FILE_NAME]),
+        }
+    }
+}
+
+/// Why an access was refused
+#[derive(Debug, Clone)]
+pub struct Blocked {
+    pub path: PathBuf,
+    pub access: Access,
+    pub reason: String,
+}
+
+impl fmt::Display for Blocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = match self.access {
+            Access::Read => "Reading",
+            Access::Write => "Writing",
+        };
+        write!(
+            f,
+            "{verb} {} is blocked: {}",
+            self.path.display(),
+            self.reason
+        )
+    }
+}
+
+struct Pattern {
+    source: String,
+    line: String,
+    negated: bool,
+    matchers: Vec<GlobMatcher>,
+}
+
+impl Pattern {
+    /// Compiles one `.gitignore` line. Returns `None` for blanks and comments.
+    fn parse(line: &str, source: &str) -> Result<Option<Self>, String> {
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return Ok(None);
+        }
+
+        let (negated, pattern) = match trimmed.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed.strip_prefix('\\').unwrap_or(trimmed)),
+        };
+        // A trailing slash only matches directories, i.e. everything below them.
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        // Patterns containing a slash are anchored at the workspace root.
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+        if pattern.is_empty() {
+            return Ok(None);
+        }
+
+        let base = if anchored || pattern.starts_with("**/") {
+            pattern.to_string()
+        } else {
+            format!("**/{pattern}")
+        };
+        let mut globs = vec![format!("{base}/**")];
+        if !dir_only {
+            globs.push(base);
+        }
+
+        let matchers = globs
+            .iter()
+            .map(|glob| {
+                GlobBuilder::new(glob)
+                    .literal_separator(true)
+                    .case_insensitive(cfg!(any(windows, target_os = "macos")))
+                    .build()
+                    .map(|glob| glob.compile_matcher())
+                    .map_err(|e| format!("Invalid pattern `{trimmed}` in {source}: {e}"))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Some(Self {
+            source: source.to_string(),
+            line: trimmed.to_string(),
+            negated,
+            matchers,
+        }))
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        self.matchers.iter().any(|matcher| matcher.is_match(path))
+    }
+}
+
+/// Checks `path` against the configured rules and the workspace's deny file.
+/// `workspace` and `path` are expected to be canonical, as returned by
+/// [`super::path_policy::resolve`].
+pub fn check(
+    workspace: &Path,
+    path: &Path,
+    access: Access,
+    rules: &FileAccessRules,
+) -> Result<(), Blocked> {
+    let relative = path.strip_prefix(workspace).unwrap_or(path);
+    let blocked = |reason: String| Blocked {
+        path: relative.to_path_buf(),
+        access,
+        reason,
+    };
+
+    // Fail closed: a broken deny-list must not open up access.
+    let layers = [
+        settings_patterns(access, rules),
+        workspace_patterns(workspace, access),
+    ];
+    // The workspace can't re-allow what the settings deny.
+    for patterns in layers {
+        let patterns = patterns.map_err(blocked)?;
+        if let Some(pattern) = denied_by(&patterns, relative) {
+            return Err(blocked(format!(
+                "matches `{}` in {}",
+                pattern.line, pattern.source
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The pattern denying `relative`, if any. The last matching pattern wins.
+fn denied_by<'a>(patterns: &'a [Pattern], relative: &Path) -> Option<&'a Pattern> {
+    patterns
+        .iter()
+        .rev()
+        .find(|pattern| pattern.matches(relative))
+        .filter(|pattern| !pattern.negated)
+}
+
+/// Checks that every pattern compiles.
+pub fn validate(patterns: &[String], source: &str) -> Result<(), String> {
+    for line in patterns {
+        Pattern::parse(line, source)?;
+    }
+    Ok(())
+}
+
+fn settings_patterns(access: Access, rules: &FileAccessRules) -> Result<Vec<Pattern>, String> {
+    let mut lines: Vec<(&str, &str)> = rules
+        .deny
+        .iter()
+        .map(|l| (l.as_str(), "settings"))
+        .collect();
+    if access == Access::Write {
+        lines.extend(rules.deny_write.iter().map(|l| (l.as_str(), "settings")));
+    }
+    parse_all(lines)
+}
+
+/// Patterns of the workspace's configuration, then of its deny file
+fn workspace_patterns(workspace: &Path, access: Access) -> Result<Vec<Pattern>, String> {
+    let config = workspace_config::load(workspace)?.file_access;
+    let config_source = workspace_config::CONFIG_FILE_NAME;
+    let mut lines: Vec<(&str, &str)> = config
+        .deny
+        .iter()
+        .map(|l| (l.as_str(), config_source))
+        .collect();
+    if access == Access::Write {
+        lines.extend(
+            config
+                .deny_write
+                .iter()
+                .map(|l| (l.as_str(), config_source)),
+        );
+    }
+    let deny_file = load_deny_file(workspace)?;
+    lines.extend(deny_file.lines().map(|l| (l, DENY_FILE_NAME)));
+    parse_all(lines)
+}
+
+fn parse_all(lines: Vec<(&str, &str)>) -> Result<Vec<Pattern>, String> {
+    let mut patterns = Vec::new();
+    for (line, source) in lines {
+        patterns.extend(Pattern::parse(line, source)?);
+    }
+    Ok(patterns)
+}
+
+fn load_deny_file(workspace: &Path) -> Result<String, String> {
+    let path = workspace.join(DENY_FILE_NAME);
+    match fs::read_to_string(&path) {
+        Ok(content) => Ok(content),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(err) => Err(format!("Failed to read {}: {err}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn denied(lines: &[&str], path: &str) -> bool {
+        let patterns = parse_all(lines.iter().map(|line| (*line, "test")).collect()).unwrap();
+        denied_by(&patterns, Path::new(path)).is_some()
+    }
+
+    #[test]
+    fn unanchored_patterns_match_at_any_depth() {
+        assert!(denied(&[".env"], ".env"));
+        assert!(denied(&[".env"], "app/config/.env"));
+        assert!(denied(&["*.pem"], "certs/server.pem"));
+        assert!(!denied(&["*.pem"], "certs/server.pem.txt"));
+    }
+
+    #[test]
+    fn patterns_with_a_slash_are_anchored() {
+        assert!(denied(&["/.git/config"], ".git/config"));
+        assert!(!denied(&["/.git/config"], "vendor/.git/config"));
+        assert!(denied(&["config/secrets.yml"], "config/secrets.yml"));
+        assert!(!denied(&["config/secrets.yml"], "app/config/secrets.yml"));
+        assert!(denied(&["**/secrets.yml"], "app/config/secrets.yml"));
+    }
+
+    #[test]
+    fn directory_patterns_match_everything_below() {
+        assert!(denied(&["secrets/"], "secrets/key.txt"));
+        assert!(denied(&["secrets/"], "app/secrets/nested/key.txt"));
+        assert!(!denied(&["secrets/"], "secrets"));
+        assert!(denied(&["secrets"], "secrets"));
+        assert!(denied(&["/.git/"], ".git/HEAD"));
+        assert!(!denied(&["/.git/"], ".github/workflows/ci.yml"));
+    }
+
+    #[test]
+    fn later_negations_re_allow() {
+        let lines = [".env", ".env.*", "!.env.example"];
+        assert!(denied(&lines, ".env.local"));
+        assert!(!denied(&lines, ".env.example"));
+        assert!(denied(&["!.env.example", ".env.*"], ".env.example"));
+        assert!(!denied(&["# comment", "", "\\#notes"], "# comment"));
+        assert!(denied(&["\\#notes"], "#notes"));
+    }
+
+    #[test]
+    fn workspace_cannot_re_allow_settings_denies() {
+        let workspace =
+            std::env::temp_dir().join(format!("open-cowork-file-access-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&workspace);
+        fs::create_dir_all(&workspace).unwrap();
+        fs::write(
+            workspace.join(DENY_FILE_NAME),
+            "!.env\nnotes/\n!notes/todo.md\n",
+        )
+        .unwrap();
+        let rules = FileAccessRules::default();
+        let check = |path: &str| check(&workspace, &workspace.join(path), Access::Read, &rules);
+
+        assert!(check(".env").is_err());
+        assert!(check("notes/draft.md").is_err());
+        assert!(check("notes/todo.md").is_ok());
+        assert!(check("src/main.rs").is_ok());
+
+        let _ = fs::remove_dir_all(&workspace);
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+use sha2::{Digest, Sha256};
+
+/// Bytes inspected to tell the encoding and spot binary files
+const SAMPLE_BYTES: usize = 8 * 1024;
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Lines `line..line + limit` of a text (1-based), or all of it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LineRange {
+    pub line: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+impl LineRange {
+    fn is_whole_file(&self) -> bool {
+        self.line.unwrap_or(1) <= 1 && self.limit.is_none()
+    }
+
+    fn skip(&self) -> usize {
+        self.line.unwrap_or(1).saturating_sub(1) as usize
+    }
+
+    fn take(&self) -> usize {
+        self.limit.map_or(usize::MAX, |limit| limit as usize)
+    }
+
+    /// Applies the range to text that is already in memory.
+    pub fn select(&self, text: &str) -> String {
+        if self.is_whole_file() {
+            return text.to_string();
+        }
+        text.split_inclusive('\n')
+            .skip(self.skip())
+            .take(self.take())
+            .collect()
+    }
+}
+
+/// The encoding of a file, from its BOM or a guess over its first bytes.
+/// Returns `None` for binary files.
+pub fn detect_encoding(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(SAMPLE_BYTES)];
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return Some(encoding);
+    }
+    if sample.contains(&0) {
+        return None;
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => return Some(UTF_8),
+        // The sample may end in the middle of a character.
+        Err(e) if e.error_len().is_none() => return Some(UTF_8),
+        Err(_) => {}
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(sample, true);
+    Some(detector.guess(None, true))
+}
+
+/// Lines read from a file
+pub struct ReadText {
+    pub text: String,
+    /// SHA-256 of the whole file; `None` when reading stopped before its end
+    pub hash: Option<[u8; 32]>,
+}
+
+/// Reads `range` of the text file at `path`, decoding legacy encodings to
+/// UTF-8. Only the requested lines are kept in memory; whole-file reads and
+/// the returned text are limited to `max_bytes`.
+pub fn read(path: &Path, range: LineRange, max_bytes: u64) -> Result<String, String> {
+    read_hashed(path, range, max_bytes).map(|read| read.text)
+}
+
+/// Like [`read`], hashing the file along the way as long as it is read to
+/// the end.
+pub fn read_hashed(path: &Path, range: LineRange, max_bytes: u64) -> Result<ReadText, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let size = file
+        .metadata()
+        .map_err(|e| format!("Failed to read file: {}", e))?
+        .len();
+    if range.is_whole_file() && size > max_bytes {
+        return Err(format!(
+            "{} is {size} bytes, more than the {max_bytes} bytes that can be read at once; request a range with `line` and `limit`",
+            path.display()
+        ));
+    }
+
+    let mut reader = BufReader::with_capacity(CHUNK_BYTES, file);
+    let mut sample = Vec::with_capacity(SAMPLE_BYTES);
+    (&mut reader)
+        .take(SAMPLE_BYTES as u64)
+        .read_to_end(&mut sample)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    let encoding =
+        detect_encoding(&sample).ok_or_else(|| format!("{} is a binary file", path.display()))?;
+
+    let mut lines = LineCollector::new(range, max_bytes);
+    let mut decoder = encoding.new_decoder_with_bom_removal();
+    let mut hasher = Sha256::new();
+    let mut chunk = sample;
+    let mut buffer = vec![0; CHUNK_BYTES];
+    let complete = loop {
+        let last = chunk.is_empty();
+        hasher.update(&chunk);
+        let mut decoded =
+            String::with_capacity(decoder.max_utf8_buffer_length(chunk.len()).unwrap_or(0));
+        let _ = decoder.decode_to_string(&chunk, &mut decoded, last);
+        if !lines.push(&decoded, last)? || last {
+            break last;
+        }
+
+        let read = reader
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+        chunk = buffer[..read].to_vec();
+    };
+
+    Ok(ReadText {
+        text: lines.output,
+        hash: complete.then(|| hasher.finalize().into()),
+    })
+}
+
+/// Splits decoded text into lines and keeps the ones inside the range.
+struct LineCollector {
+    range: LineRange,
+    max_bytes: u64,
+    pending: String,
+    seen: usize,
+    output: String,
+}
+
+impl LineCollector {
+    fn new(range: LineRange, max_bytes: u64) -> Self {
+        Self {
+            range,
+            max_bytes,
+            pending: String::new(),
+            seen: 0,
+            output: String::new(),
+        }
+    }
+
+    /// Returns false once every requested line has been collected.
+    fn push(&mut self, text: &str, last: bool) -> Result<bool, String> {
+        self.pending.push_str(text);
+        let mut start = 0;
+        while let Some(end) = self.pending[start..].find('\n') {
+            let end = start + end + 1;
+            if !self.keep(start, end)? {
+                return Ok(false);
+            }
+            start = end;
+        }
+        if last && start < self.pending.len() {
+            let end = self.pending.len();
+            self.keep(start, end)?;
+            start = end;
+        }
+        self.pending.drain(..start);
+        Ok(true)
+    }
+
+    fn keep(&mut self, start: usize, end: usize) -> Result<bool, String> {
+        let index = self.seen;
+        self.seen += 1;
+        if index < self.range.skip() {
+            return Ok(true);
+        }
+        if index - self.range.skip() >= self.range.take() {
+            return Ok(false);
+        }
+
+        self.output.push_str(&self.pending[start..end]);
+        if self.output.len() as u64 > self.max_bytes {
+            return Err(format!(
+                "The requested lines exceed the {} byte read limit; request fewer lines",
+                self.max_bytes
+            ));
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+
+    use super::*;
+
+    /// A scratch file removed when dropped
+    struct TempFile(std::path::PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, bytes: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "open-cowork-file-read-{}-{name}",
+                std::process::id()
+            ));
+            fs::write(&path, bytes).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn range(line: u32, limit: u32) -> LineRange {
+        LineRange {
+            line: Some(line),
+            limit: Some(limit),
+        }
+    }
+
+    #[test]
+    fn collects_lines_split_across_chunks() {
+        let mut lines = LineCollector::new(range(2, 2), u64::MAX);
+        for piece in ["fir", "st\nsec", "ond\n", "thi", "rd\nfourth\n"] {
+            if !lines.push(piece, false).unwrap() {
+                break;
+            }
+        }
+        assert_eq!(lines.output, "second\nthird\n");
+
+        let mut lines = LineCollector::new(range(2, 5), u64::MAX);
+        assert!(lines.push("a\nb\nc", false).unwrap());
+        assert!(lines.push("", true).unwrap());
+        assert_eq!(lines.output, "b\nc");
+    }
+
+    #[test]
+    fn reads_ranges_beyond_the_first_chunk() {
+        let text: String = (1..=20_000).map(|n| format!("line {n}\n")).collect();
+        assert!(text.len() > 2 * CHUNK_BYTES);
+        let file = TempFile::new("long.txt", text.as_bytes());
+
+        let read = read_hashed(&file.0, range(15_000, 3), u64::MAX).unwrap();
+        assert_eq!(read.text, "line 15000\nline 15001\nline 15002\n");
+        assert!(read.hash.is_none());
+
+        let read = read_hashed(&file.0, range(19_999, 10), u64::MAX).unwrap();
+        assert_eq!(read.text, "line 19999\nline 20000\n");
+        let expected: [u8; 32] = Sha256::digest(text.as_bytes()).into();
+        assert_eq!(read.hash, Some(expected));
+
+        assert!(read_hashed(&file.0, LineRange::default(), 1024).is_err());
+        assert!(read_hashed(&file.0, range(1, 1000), 1024).is_err());
+    }
+
+    #[test]
+    fn decodes_legacy_encodings() {
+        let text = "Le café est très chaud, déjà prêt à être servi.\nCrème brûlée\n";
+        let file = TempFile::new("latin1.txt", &WINDOWS_1252.encode(text).0);
+        assert_eq!(read(&file.0, LineRange::default(), u64::MAX).unwrap(), text);
+        assert_eq!(
+            read(&file.0, range(2, 1), u64::MAX).unwrap(),
+            "Crème brûlée\n"
+        );
+
+        let text = "日本語のテキストファイルです。\n二行目もあります。\n";
+        let file = TempFile::new("sjis.txt", &SHIFT_JIS.encode(text).0);
+        assert_eq!(read(&file.0, LineRange::default(), u64::MAX).unwrap(), text);
+    }
+
+    #[test]
+    fn strips_the_bom_and_rejects_binary_files() {
+        let file = TempFile::new("bom.txt", b"\xef\xbb\xbfhello\n");
+        assert_eq!(
+            read(&file.0, LineRange::default(), u64::MAX).unwrap(),
+            "hello\n"
+        );
+
+        let utf16: Vec<u8> = [0xff, 0xfe]
+            .into_iter()
+            .chain("hé\n".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let file = TempFile::new("utf16.txt", &utf16);
+        assert_eq!(
+            read(&file.0, LineRange::default(), u64::MAX).unwrap(),
+            "hé\n"
+        );
+
+        let file = TempFile::new("binary.bin", b"\x00\x01\x02\x03");
+        assert!(read(&file.0, LineRange::default(), u64::MAX).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+
+/// What a file looked like when the agent last read or wrote it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Version {
+    len: u64,
+    modified: Option<SystemTime>,
+    /// `None` when the agent only read part of the file
+    hash: Option<[u8; 32]>,
+}
+
+lazy_static::lazy_static! {
+    /// Versions of the files each session has seen, by absolute path
+    static ref SEEN: Mutex<HashMap<String, HashMap<PathBuf, Version>>> = Mutex::new(HashMap::new());
+}
+
+/// Remembers the file as the session's agent has just written it.
+pub fn record(session_id: &str, path: &Path) {
+    if let Ok(hash) = hash_file(path) {
+        record_read(session_id, path, Some(hash));
+    }
+}
+
+/// Remembers the file as the session's agent has just read it, with the
+/// hash taken while reading. Partial reads of large files skip the hash.
+pub fn record_read(session_id: &str, path: &Path, hash: Option<[u8; 32]>) {
+    let Ok(metadata) = fs::metadata(path) else {
+        return;
+    };
+    let version = Version {
+        len: metadata.len(),
+        modified: metadata.modified().ok(),
+        hash,
+    };
+    SEEN.lock()
+        .unwrap()
+        .entry(session_id.to_string())
+        .or_default()
+        .insert(path.to_path_buf(), version);
+}
+
+/// Fails if `path` changed on disk since the session's agent last saw it,
+/// e.g. because the user edited it in the meantime. Files the agent never
+/// read are not checked.
+pub fn check(session_id: &str, path: &Path) -> Result<(), String> {
+    let Some(seen) = SEEN
+        .lock()
+        .unwrap()
+        .get(session_id)
+        .and_then(|files| files.get(path))
+        .cloned()
+    else {
+        return Ok(());
+    };
+
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!(
+                "{} was deleted since the agent last read it",
+                path.display()
+            ))
+        }
+        Err(e) => return Err(format!("Failed to inspect file: {}", e)),
+    };
+    if metadata.len() == seen.len && metadata.modified().ok() == seen.modified {
+        return Ok(());
+    }
+
+    // The timestamp alone changes on a plain save without edits. Without a
+    // hash of what the agent saw, any change counts.
+    let unchanged = match seen.hash {
+        Some(seen_hash) => {
+            hash_file(path).map_err(|e| format!("Failed to read file: {}", e))? == seen_hash
+        }
+        None => false,
+    };
+    if !unchanged {
+        return Err(format!(
+            "{} was modified on disk since the agent last read it; read it again before writing",
+            path.display()
+        ));
+    }
+
+    record(session_id, path);
+    Ok(())
+}
+
+fn hash_file(path: &Path) -> std::io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+pub fn forget(session_id: &str) {
+    SEEN.lock().unwrap().remove(session_id);
+}
@@ -0,0 +1,282 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+};
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use sha2::{Digest, Sha256};
+
+use super::file_read;
+use crate::transcript;
+
+/// Previous versions kept per session before the oldest is dropped
+pub const MAX_BACKUPS: usize = 50;
+
+const UTF8_BOM: &str = "\u{feff}";
+
+static BACKUP_DIR: OnceLock<PathBuf> = OnceLock::new();
+static NEXT_TEMP_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Sets the directory backups are stored in. Called once during setup.
+pub fn init(dir: PathBuf) -> Result<(), String> {
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create backup directory: {}", e))?;
+    let _ = BACKUP_DIR.set(dir);
+    Ok(())
+}
+
+/// Conventions of an existing text file that a rewrite keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TextStyle {
+    encoding: &'static Encoding,
+    bom: bool,
+    crlf: bool,
+    trailing_newline: bool,
+}
+
+impl TextStyle {
+    /// Returns `None` for binary files, which are replaced as they are.
+    fn detect(bytes: &[u8]) -> Option<Self> {
+        let encoding = file_read::detect_encoding(bytes)?;
+        let (text, bom) = match Encoding::for_bom(bytes) {
+            Some((encoding, bom_len)) => (
+                encoding.decode_without_bom_handling(&bytes[bom_len..]).0,
+                true,
+            ),
+            None => (encoding.decode_without_bom_handling(bytes).0, false),
+        };
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        Some(Self {
+            encoding,
+            bom,
+            crlf: crlf > lf,
+            trailing_newline: text.ends_with('\n'),
+        })
+    }
+
+    fn apply(&self, content: &str) -> Result<Vec<u8>, String> {
+        let content = content.strip_prefix(UTF8_BOM).unwrap_or(content);
+        let mut body = content.replace("\r\n", "\n");
+        if self.trailing_newline && !body.is_empty() && !body.ends_with('\n') {
+            body.push('\n');
+        } else if !self.trailing_newline && body.ends_with('\n') {
+            // Agents end their content with a newline; further blank lines
+            // are intended.
+            body.pop();
+        }
+        if self.crlf {
+            body = body.replace('\n', "\r\n");
+        }
+        if self.bom {
+            body.insert_str(0, UTF8_BOM);
+        }
+        encode(&body, self.encoding)
+    }
+}
+
+/// Encodes UTF-8 text, BOM included, back into the file's encoding.
+fn encode(text: &str, encoding: &'static Encoding) -> Result<Vec<u8>, String> {
+    // encoding_rs only decodes UTF-16, so that is done by hand.
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        return Ok(text
+            .encode_utf16()
+            .flat_map(|unit| {
+                if encoding == UTF_16LE {
+                    unit.to_le_bytes()
+                } else {
+                    unit.to_be_bytes()
+                }
+            })
+            .collect());
+    }
+
+    let (bytes, _, unmappable) = encoding.encode(text);
+    if unmappable {
+        return Err(format!(
+            "The new content has characters that cannot be written in the file's {} encoding",
+            encoding.name()
+        ));
+    }
+    Ok(bytes.into_owned())
+}
+
+/// Replaces `path` with `content` without ever leaving a partially written
+/// file behind. An existing file keeps its encoding, permissions, line
+/// endings, BOM and trailing newline, and its previous contents are backed
+/// up for the session.
+pub fn write(session_id: &str, path: &Path, content: &str) -> Result<(), String> {
+    let existing = match fs::read(path) {
+        Ok(bytes) => Some(bytes),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Failed to read file: {}", e)),
+    };
+
+    let bytes = match existing
+        .as_deref()
+        .filter(|previous| !previous.is_empty())
+        .and_then(TextStyle::detect)
+    {
+        Some(style) => style.apply(content)?,
+        None => content.as_bytes().to_vec(),
+    };
+
+    let parent = path
+        .parent()
+        .ok_or_else(|| format!("Invalid file path: {}", path.display()))?;
+    fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+
+    if let Some(previous) = &existing {
+        backup(session_id, path, previous)?;
+    }
+
+    let temp = parent.join(format!(
+        ".{}.{}-{}.tmp",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        std::process::id(),
+        NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = write_temp(&temp, path, &bytes).and_then(|()| {
+        fs::rename(&temp, path).map_err(|e| format!("Failed to replace file: {}", e))
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn write_temp(temp: &Path, target: &Path, bytes: &[u8]) -> Result<(), String> {
+    let mut file = File::create(temp).map_err(|e| format!("Failed to write file: {}", e))?;
+    file.write_all(bytes)
+        .map_err(|e| format!("Failed to write file: {}", e))?;
+    if let Ok(metadata) = fs::metadata(target) {
+        file.set_permissions(metadata.permissions())
+            .map_err(|e| format!("Failed to copy permissions: {}", e))?;
+    }
+    file.sync_all()
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+/// Copies the previous contents of `path` into the session's backup
+/// directory and drops the oldest backups beyond [`MAX_BACKUPS`].
+fn backup(session_id: &str, path: &Path, previous: &[u8]) -> Result<(), String> {
+    let Some(dir) = BACKUP_DIR.get() else {
+        return Ok(());
+    };
+    let dir = dir.join(transcript::file_stem(session_id));
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create backup directory: {}", e))?;
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    // The path hash keeps backups of equally named files apart.
+    let digest = Sha256::digest(path.as_os_str().as_encoded_bytes());
+    let path_hash: String = digest[..4].iter().map(|b| format!("{b:02x}")).collect();
+    let name = format!(
+        "{timestamp:016}-{:06}-{path_hash}-{}",
+        NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed),
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    fs::write(dir.join(name), previous).map_err(|e| format!("Failed to write backup: {}", e))?;
+
+    let mut backups: Vec<_> = fs::read_dir(&dir)
+        .map_err(|e| format!("Failed to list backups: {}", e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    if backups.len() > MAX_BACKUPS {
+        backups.sort();
+        for old in &backups[..backups.len() - MAX_BACKUPS] {
+            let _ = fs::remove_file(old);
+        }
+    }
+    Ok(())
+}
+
+/// Removes the backups of a session.
+pub fn delete_backups(session_id: &str) -> Result<(), String> {
+    let Some(dir) = BACKUP_DIR.get() else {
+        return Ok(());
+    };
+    match fs::remove_dir_all(dir.join(transcript::file_stem(session_id))) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("Failed to delete backups: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{SHIFT_JIS, UTF_8, WINDOWS_1252};
+
+    use super::*;
+
+    fn rewrite(existing: &[u8], content: &str) -> Vec<u8> {
+        TextStyle::detect(existing).unwrap().apply(content).unwrap()
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        assert_eq!(rewrite(b"a\r\nb\r\n", "a\nc\n"), b"a\r\nc\r\n");
+        assert_eq!(rewrite(b"a\r\nb\r\n", "a\r\nc\n"), b"a\r\nc\r\n");
+        assert_eq!(rewrite(b"a\nb\n", "a\r\nc\r\n"), b"a\nc\n");
+    }
+
+    #[test]
+    fn keeps_the_bom() {
+        let style = TextStyle::detect(b"\xef\xbb\xbfa\n").unwrap();
+        assert!(style.bom);
+        assert_eq!(style.apply("b\n").unwrap(), b"\xef\xbb\xbfb\n");
+        assert_eq!(style.apply("\u{feff}b\n").unwrap(), b"\xef\xbb\xbfb\n");
+        assert_eq!(rewrite(b"a\n", "\u{feff}b\n"), b"b\n");
+    }
+
+    #[test]
+    fn keeps_utf16() {
+        let existing: Vec<u8> = [0xff, 0xfe]
+            .into_iter()
+            .chain("a\n".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let expected: Vec<u8> = [0xff, 0xfe]
+            .into_iter()
+            .chain("é\n".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        assert_eq!(rewrite(&existing, "é\n"), expected);
+    }
+
+    #[test]
+    fn keeps_legacy_encodings() {
+        let (existing, _, _) =
+            WINDOWS_1252.encode("Le café est très chaud, déjà prêt à être servi.\n");
+        let style = TextStyle::detect(&existing).unwrap();
+        assert_eq!(style.encoding, WINDOWS_1252);
+        assert_eq!(
+            style.apply("crème brûlée\n").unwrap(),
+            b"cr\xe8me br\xfbl\xe9e\n"
+        );
+        assert!(style.apply("日本語\n").is_err());
+
+        assert_eq!(encode("日本", SHIFT_JIS).unwrap(), b"\x93\xfa\x96\x7b");
+        assert_eq!(TextStyle::detect(b"plain\n").unwrap().encoding, UTF_8);
+    }
+
+    #[test]
+    fn keeps_the_trailing_newline_style() {
+        assert_eq!(rewrite(b"a\n", "b"), b"b\n");
+        assert_eq!(rewrite(b"a\n", "b\n\n"), b"b\n\n");
+        assert_eq!(rewrite(b"a", "b\n"), b"b");
+        assert_eq!(rewrite(b"a", "b"), b"b");
+        // Only the newline the agent ended with is dropped.
+        assert_eq!(rewrite(b"a", "b\n\n"), b"b\n");
+        assert_eq!(rewrite(b"a\r\nb", "c\r\n\r\n"), b"c\r\n");
+    }
+
+    #[test]
+    fn binary_files_have_no_style() {
+        assert!(TextStyle::detect(b"\x00\x01\x02").is_none());
+    }
+}
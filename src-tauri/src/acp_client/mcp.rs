@@ -0,0 +1,293 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+};
+
+use agent_client_protocol as acp;
+use serde::{Deserialize, Serialize};
+
+use crate::{settings, workspace_config};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum McpTransport {
+    #[default]
+    Stdio,
+    Http,
+    Sse,
+}
+
+/// An MCP server handed to the agent for every session. Stdio servers need a
+/// `command`, HTTP and SSE servers a `url`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct McpServerConfig {
+    pub name: String,
+    #[serde(default)]
+    pub transport: McpTransport,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl McpServerConfig {
+    /// Checks the definition and converts it into its ACP form. Relative
+    /// commands with a directory part run from the workspace.
+    fn to_acp(&self, workspace: &Path) -> Result<acp::McpServer, String> {
+        if self.name.trim().is_empty() {
+            return Err("MCP server without a name".to_string());
+        }
+
+        match self.transport {
+            McpTransport::Stdio => {
+                let command = self
+                    .command
+                    .as_deref()
+                    .filter(|command| !command.trim().is_empty())
+                    .ok_or_else(|| format!("MCP server '{}' has no command", self.name))?;
+                if self.url.is_some() || !self.headers.is_empty() {
+                    return Err(format!(
+                        "MCP server '{}' uses stdio and cannot have a url or headers",
+                        self.name
+                    ));
+                }
+                let command = Path::new(command);
+                let command = if command.is_relative() && command.components().count() > 1 {
+                    workspace.join(command)
+                } else {
+                    command.to_path_buf()
+                };
+                Ok(acp::McpServer::Stdio(
+                    acp::McpServerStdio::new(&self.name, command)
+                        .args(self.args.clone())
+                        .env(
+                            self.env
+                                .iter()
+                                .map(|(name, value)| acp::EnvVariable::new(name, value))
+                                .collect(),
+                        ),
+                ))
+            }
+            McpTransport::Http | McpTransport::Sse => {
+                let url = self
+                    .url
+                    .as_deref()
+                    .ok_or_else(|| format!("MCP server '{}' has no url", self.name))?;
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    return Err(format!(
+                        "MCP server '{}' has an invalid url '{url}'; expected http:// or https://",
+                        self.name
+                    ));
+                }
+                if self.command.is_some() || !self.args.is_empty() || !self.env.is_empty() {
+                    return Err(format!(
+                        "MCP server '{}' connects over {} and cannot have a command, args or env",
+                        self.name,
+                        self.transport.name()
+                    ));
+                }
+                let headers = self
+                    .headers
+                    .iter()
+                    .map(|(name, value)| acp::HttpHeader::new(name, value))
+                    .collect();
+                Ok(match self.transport {
+                    McpTransport::Http => acp::McpServer::Http(
+                        acp::McpServerHttp::new(&self.name, url).headers(headers),
+                    ),
+                    _ => acp::McpServer::Sse(
+                        acp::McpServerSse::new(&self.name, url).headers(headers),
+                    ),
+                })
+            }
+        }
+    }
+}
+
+impl McpTransport {
+    fn name(self) -> &'static str {
+        match self {
+            McpTransport::Stdio => "stdio",
+            McpTransport::Http => "HTTP",
+            McpTransport::Sse => "SSE",
+        }
+    }
+
+    fn supported_by(self, capabilities: &acp::McpCapabilities) -> bool {
+        match self {
+            McpTransport::Stdio => true,
+            McpTransport::Http => capabilities.http,
+            McpTransport::Sse => capabilities.sse,
+        }
+    }
+}
+
+/// Checks server definitions and that their names are unique.
+pub fn validate(servers: &[McpServerConfig], workspace: &Path) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for server in servers {
+        server.to_acp(workspace)?;
+        if !names.insert(server.name.as_str()) {
+            return Err(format!("MCP server '{}' is defined twice", server.name));
+        }
+    }
+    Ok(())
+}
+
+/// The MCP servers of a session, and the configured ones the agent can't use
+pub struct SessionMcpServers {
+    pub servers: Vec<acp::McpServer>,
+    pub skipped: Vec<String>,
+}
+
+/// Collects the MCP servers from the settings and the workspace's
+/// configuration, where a server replaces a global one of the same name.
+/// The workspace's servers are left out until the user approved its
+/// configuration. Invalid definitions are errors; servers over transports
+/// the agent doesn't support are skipped.
+pub fn for_session(
+    workspace: &Path,
+    capabilities: &acp::McpCapabilities,
+) -> Result<SessionMcpServers, String> {
+    let workspace_servers = workspace_config::load_approved(workspace)?.mcp_servers;
+    merge(
+        &settings::current().mcp_servers,
+        &workspace_servers,
+        workspace,
+        capabilities,
+    )
+}
+
+fn merge(
+    global_servers: &[McpServerConfig],
+    workspace_servers: &[McpServerConfig],
+    workspace: &Path,
+    capabilities: &acp::McpCapabilities,
+) -> Result<SessionMcpServers, String> {
+    let overridden: HashSet<&str> = workspace_servers
+        .iter()
+        .map(|server| server.name.as_str())
+        .collect();
+
+    let mut names = HashSet::new();
+    let mut result = SessionMcpServers {
+        servers: Vec::new(),
+        skipped: Vec::new(),
+    };
+    for config in global_servers
+        .iter()
+        .filter(|server| !overridden.contains(server.name.as_str()))
+        .chain(workspace_servers)
+    {
+        let server = config.to_acp(workspace)?;
+        if !names.insert(config.name.as_str()) {
+            return Err(format!("MCP server '{}' is defined twice", config.name));
+        }
+        if !config.transport.supported_by(capabilities) {
+            result.skipped.push(format!(
+                "MCP server '{}' was skipped: the agent does not support {} MCP servers",
+                config.name,
+                config.transport.name()
+            ));
+            continue;
+        }
+        result.servers.push(server);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn stdio(name: &str, command: &str) -> McpServerConfig {
+        McpServerConfig {
+            name: name.to_string(),
+            transport: McpTransport::Stdio,
+            command: Some(command.to_string()),
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            url: None,
+            headers: BTreeMap::new(),
+        }
+    }
+
+    fn remote(name: &str, transport: McpTransport) -> McpServerConfig {
+        McpServerConfig {
+            transport,
+            command: None,
+            url: Some("https://mcp.example.com".to_string()),
+            ..stdio(name, "")
+        }
+    }
+
+    fn stdio_command(server: &acp::McpServer) -> PathBuf {
+        match server {
+            acp::McpServer::Stdio(server) => server.command.clone(),
+            other => panic!("expected a stdio server, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn workspace_servers_replace_global_ones_by_name() {
+        let workspace = Path::new("/work");
+        let global = [
+            stdio("docs", "docs-server"),
+            stdio("search", "search-server"),
+        ];
+        let local = [stdio("docs", "./tools/docs"), stdio("db", "db-server")];
+
+        let merged = merge(&global, &local, workspace, &acp::McpCapabilities::new()).unwrap();
+        let commands: Vec<PathBuf> = merged.servers.iter().map(stdio_command).collect();
+        assert_eq!(
+            commands,
+            [
+                PathBuf::from("search-server"),
+                workspace.join("tools/docs"),
+                PathBuf::from("db-server"),
+            ]
+        );
+        assert!(merged.skipped.is_empty());
+
+        let twice = [stdio("db", "a"), stdio("db", "b")];
+        assert!(merge(&[], &twice, workspace, &acp::McpCapabilities::new()).is_err());
+        assert!(merge(
+            &[],
+            &[stdio("", "a")],
+            workspace,
+            &acp::McpCapabilities::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn servers_over_unsupported_transports_are_skipped() {
+        let workspace = Path::new("/work");
+        let servers = [
+            stdio("local", "server"),
+            remote("http", McpTransport::Http),
+            remote("sse", McpTransport::Sse),
+        ];
+
+        let merged = merge(&servers, &[], workspace, &acp::McpCapabilities::new()).unwrap();
+        assert_eq!(merged.servers.len(), 1);
+        assert_eq!(merged.skipped.len(), 2);
+        assert!(merged.skipped[0].contains("'http'"));
+
+        let capabilities = acp::McpCapabilities::new().http(true);
+        let merged = merge(&servers, &[], workspace, &capabilities).unwrap();
+        assert_eq!(merged.servers.len(), 2);
+        assert!(matches!(merged.servers[1], acp::McpServer::Http(_)));
+        assert_eq!(merged.skipped.len(), 1);
+        assert!(merged.skipped[0].contains("'sse'"));
+    }
+}
@@ -1 +1,18 @@
+pub mod buffers;
 pub mod client;
+pub mod commands;
+pub mod file_access;
+pub mod file_read;
+pub mod file_versions;
+pub mod file_write;
+pub mod mcp;
+pub mod models;
+pub mod modes;
+pub mod notifications;
+pub mod path_policy;
+pub mod permission;
+pub mod policy;
+pub mod selection;
+pub mod staging;
+pub mod terminal;
+pub mod turn_state;
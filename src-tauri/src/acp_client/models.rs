@@ -0,0 +1,51 @@
+use agent_client_protocol as acp;
+use serde::Serialize;
+
+use super::selection::{Selection, SelectionOption, SelectionStore};
+
+/// Models an agent lets a session choose from
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionModels {
+    pub current_model_id: String,
+    pub available_models: Vec<SelectionOption>,
+}
+
+impl SessionModels {
+    pub fn has_model(&self, model_id: &str) -> bool {
+        self.available_models
+            .iter()
+            .any(|model| model.id == model_id)
+    }
+}
+
+impl Selection for SessionModels {
+    fn current(&self) -> &str {
+        &self.current_model_id
+    }
+
+    fn select(&mut self, model_id: &str) {
+        self.current_model_id = model_id.to_string();
+    }
+}
+
+impl From<acp::SessionModelState> for SessionModels {
+    fn from(state: acp::SessionModelState) -> Self {
+        Self {
+            current_model_id: state.current_model_id.0.to_string(),
+            available_models: state
+                .available_models
+                .into_iter()
+                .map(|model| SelectionOption {
+                    id: model.model_id.0.to_string(),
+                    name: model.name,
+                    description: model.description,
+                })
+                .collect(),
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Session models per UI session id, for agents that report them
+    pub static ref SESSION_MODELS: SelectionStore<SessionModels> = SelectionStore::new();
+}
@@ -0,0 +1,49 @@
+use agent_client_protocol as acp;
+use serde::Serialize;
+
+use super::selection::{Selection, SelectionOption, SelectionStore};
+
+/// Modes an agent offers for a session, such as "ask", "code" or "architect"
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionModes {
+    pub current_mode_id: String,
+    pub available_modes: Vec<SelectionOption>,
+}
+
+impl SessionModes {
+    pub fn has_mode(&self, mode_id: &str) -> bool {
+        self.available_modes.iter().any(|mode| mode.id == mode_id)
+    }
+}
+
+impl Selection for SessionModes {
+    fn current(&self) -> &str {
+        &self.current_mode_id
+    }
+
+    fn select(&mut self, mode_id: &str) {
+        self.current_mode_id = mode_id.to_string();
+    }
+}
+
+impl From<acp::SessionModeState> for SessionModes {
+    fn from(state: acp::SessionModeState) -> Self {
+        Self {
+            current_mode_id: state.current_mode_id.0.to_string(),
+            available_modes: state
+                .available_modes
+                .into_iter()
+                .map(|mode| SelectionOption {
+                    id: mode.id.0.to_string(),
+                    name: mode.name,
+                    description: mode.description,
+                })
+                .collect(),
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Session modes per UI session id, for agents that support modes
+    pub static ref SESSION_MODES: SelectionStore<SessionModes> = SelectionStore::new();
+}
@@ -0,0 +1,212 @@
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use agent_client_protocol as acp;
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    sync::watch,
+};
+
+/// Only reached if a notification was read but never handled, e.g. because
+/// its handler panicked
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Session notifications of one agent session
+#[derive(Debug, Default, Clone, Copy)]
+struct Counts {
+    /// Read from the agent's output
+    read: u64,
+    handled: u64,
+}
+
+/// Lets a turn wait until the client has handled every session notification
+/// the agent sent before answering the prompt. The agent's output is read in
+/// order, but notifications are handled on tasks of their own, so the prompt
+/// response can overtake them.
+#[derive(Clone)]
+pub struct NotificationTracker {
+    counts: Arc<Mutex<HashMap<String, Counts>>>,
+    changed: Arc<watch::Sender<()>>,
+}
+
+impl NotificationTracker {
+    pub fn new() -> Self {
+        Self {
+            counts: Arc::default(),
+            changed: Arc::new(watch::channel(()).0),
+        }
+    }
+
+    /// Wraps the agent's stdout to count session notifications as they are read.
+    pub fn reader<R: AsyncRead + Unpin>(&self, inner: R) -> CountingReader<R> {
+        CountingReader {
+            inner,
+            line: Vec::new(),
+            tracker: self.clone(),
+        }
+    }
+
+    /// Counts the notification as handled once the returned guard is dropped.
+    pub fn handling(&self, agent_session_id: &str) -> Handling<'_> {
+        Handling {
+            tracker: self,
+            agent_session_id: agent_session_id.to_string(),
+        }
+    }
+
+    /// Waits until every notification of the session read so far is handled.
+    pub async fn flush(&self, agent_session_id: &str) {
+        let mut changed = self.changed.subscribe();
+        let flushed = changed.wait_for(|_| {
+            self.counts
+                .lock()
+                .unwrap()
+                .get(agent_session_id)
+                .is_none_or(|counts| counts.handled >= counts.read)
+        });
+        if tokio::time::timeout(FLUSH_TIMEOUT, flushed).await.is_err() {
+            log::warn!("Gave up waiting for the notifications of session {agent_session_id}");
+        }
+    }
+
+    pub fn forget(&self, agent_session_id: &str) {
+        self.counts.lock().unwrap().remove(agent_session_id);
+    }
+
+    fn line_read(&self, line: &[u8]) {
+        #[derive(Deserialize)]
+        struct Message {
+            id: Option<serde_json::Value>,
+            method: Option<String>,
+            params: Option<serde_json::Value>,
+        }
+
+        // Counted exactly when the connection will hand it to the client.
+        let Ok(Message {
+            id: None,
+            method: Some(method),
+            params: Some(params),
+        }) = serde_json::from_slice(line)
+        else {
+            return;
+        };
+        if method != "session/update" {
+            return;
+        }
+        if let Ok(notification) = serde_json::from_value::<acp::SessionNotification>(params) {
+            self.counts
+                .lock()
+                .unwrap()
+                .entry(notification.session_id.0.to_string())
+                .or_default()
+                .read += 1;
+        }
+    }
+}
+
+impl Default for NotificationTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Handling<'a> {
+    tracker: &'a NotificationTracker,
+    agent_session_id: String,
+}
+
+impl Drop for Handling<'_> {
+    fn drop(&mut self) {
+        self.tracker
+            .counts
+            .lock()
+            .unwrap()
+            .entry(std::mem::take(&mut self.agent_session_id))
+            .or_default()
+            .handled += 1;
+        self.tracker.changed.send_replace(());
+    }
+}
+
+/// The agent's output, counting session notifications line by line
+pub struct CountingReader<R> {
+    inner: R,
+    line: Vec<u8>,
+    tracker: NotificationTracker,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = &poll {
+            let this = &mut *self;
+            for chunk in buf.filled()[before..].split_inclusive(|byte| *byte == b'\n') {
+                this.line.extend_from_slice(chunk);
+                if chunk.ends_with(b"\n") {
+                    this.tracker.line_read(&this.line);
+                    this.line.clear();
+                }
+            }
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{poll_fn, Future};
+
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    fn update(session_id: &str) -> String {
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"session/update","params":{{"sessionId":"{session_id}","update":{{"sessionUpdate":"agent_message_chunk","content":{{"type":"text","text":"hi"}}}}}}}}"#
+        )
+    }
+
+    #[tokio::test]
+    async fn flush_waits_for_notifications_read_before_it() {
+        let tracker = NotificationTracker::new();
+        let output = format!(
+            "{}\n{}\n{}\n{}\n",
+            update("a"),
+            update("b"),
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/request_permission","params":{}}"#,
+            update("a"),
+        );
+        // Split mid-line, like reads from a pipe
+        let (first, second) = output.as_bytes().split_at(70);
+        let mut read = Vec::new();
+        tracker
+            .reader(first.chain(second))
+            .read_to_end(&mut read)
+            .await
+            .unwrap();
+        assert_eq!(read, output.as_bytes());
+        assert_eq!(tracker.counts.lock().unwrap()["a"].read, 2);
+        assert_eq!(tracker.counts.lock().unwrap()["b"].read, 1);
+
+        let flushed = tracker.flush("a");
+        tokio::pin!(flushed);
+        drop(tracker.handling("a"));
+        drop(tracker.handling("b"));
+        let pending = poll_fn(|cx| Poll::Ready(flushed.as_mut().poll(cx).is_pending())).await;
+        assert!(pending);
+        drop(tracker.handling("a"));
+        flushed.await;
+        tracker.flush("unknown").await;
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    fmt, fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// Why a path was refused by [`resolve`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    /// The workspace root itself could not be resolved
+    WorkspaceUnavailable(String),
+    /// The path points outside the workspace before following any links
+    OutsideWorkspace(PathBuf),
+    /// A symlink inside the workspace points outside of it
+    SymlinkEscape { link: PathBuf, target: PathBuf },
+    /// A symlink whose target does not exist; writing through it could
+    /// create a file anywhere
+    DanglingSymlink(PathBuf),
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::WorkspaceUnavailable(err) => {
+                write!(f, "Workspace is not accessible: {err}")
+            }
+            PathError::OutsideWorkspace(path) => {
+                write!(
+                    f,
+                    "Access outside workspace is not allowed: {}",
+                    path.display()
+                )
+            }
+            PathError::SymlinkEscape { link, target } => write!(
+                f,
+                "Symlink {} points outside the workspace: {}",
+                link.display(),
+                target.display()
+            ),
+            PathError::DanglingSymlink(link) => {
+                write!(f, "Refusing to follow dangling symlink: {}", link.display())
+            }
+        }
+    }
+}
+
+/// Resolves `path` (absolute, or relative to `workspace`) to the location
+/// that file operations must use, refusing anything that leaves the
+/// workspace. Works for paths that do not exist yet: the deepest existing
+/// ancestor is resolved through the filesystem and the remaining components
+/// are appended to it.
+pub fn resolve(workspace: &Path, path: &Path) -> Result<PathBuf, PathError> {
+    let root = workspace
+        .canonicalize()
+        .map_err(|e| PathError::WorkspaceUnavailable(e.to_string()))?;
+    let lexical_root = normalize(workspace);
+
+    // Absolute paths replace the root when joined.
+    let candidate = normalize(&lexical_root.join(path));
+    let relative = candidate
+        .strip_prefix(&lexical_root)
+        .or_else(|_| candidate.strip_prefix(&root))
+        .map_err(|_| PathError::OutsideWorkspace(path.to_path_buf()))?
+        .to_path_buf();
+
+    let mut resolved = root.clone();
+    let mut components = relative.components();
+    while let Some(component) = components.next() {
+        let next = resolved.join(component);
+        match fs::symlink_metadata(&next) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                let target = next
+                    .canonicalize()
+                    .map_err(|_| PathError::DanglingSymlink(next.clone()))?;
+                if !target.starts_with(&root) {
+                    return Err(PathError::SymlinkEscape { link: next, target });
+                }
+                resolved = target;
+            }
+            Ok(_) => resolved = next,
+            Err(_) => {
+                // Nothing exists from here on, so nothing left can be a link.
+                resolved = next;
+                resolved.extend(components);
+                break;
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Resolves an absolute `path` the way [`resolve`] does for paths inside
+/// the workspace, without checking where it leads: the deepest existing
+/// ancestor is canonicalized and the remaining components are appended.
+pub fn canonicalize_lenient(path: &Path) -> PathBuf {
+    let path = normalize(path);
+    let mut existing = path.as_path();
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            let rest = path.strip_prefix(existing).unwrap_or(Path::new(""));
+            return if rest.as_os_str().is_empty() {
+                canonical
+            } else {
+                canonical.join(rest)
+            };
+        }
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => return path,
+        }
+    }
+}
+
+/// Resolves `.` and `..` without touching the filesystem.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory holding `workspace/` and a sibling `outside/`.
+    struct Sandbox {
+        base: PathBuf,
+    }
+
+    impl Sandbox {
+        fn new(name: &str) -> Self {
+            let base = std::env::temp_dir().join(format!(
+                "open-cowork-path-policy-{}-{name}",
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&base);
+            fs::create_dir_all(base.join("workspace/src")).unwrap();
+            fs::create_dir_all(base.join("outside")).unwrap();
+            fs::write(base.join("workspace/src/main.rs"), "fn main() {}").unwrap();
+            fs::write(base.join("outside/secret.txt"), "secret").unwrap();
+            Self { base }
+        }
+
+        fn workspace(&self) -> PathBuf {
+            self.base.join("workspace")
+        }
+
+        fn root(&self) -> PathBuf {
+            self.workspace().canonicalize().unwrap()
+        }
+    }
+
+    impl Drop for Sandbox {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.base);
+        }
+    }
+
+    #[derive(Debug)]
+    enum Expect {
+        /// Resolves to this path relative to the canonical workspace
+        Inside(&'static str),
+        Outside,
+        SymlinkEscape,
+        Dangling,
+    }
+
+    #[cfg(unix)]
+    fn link(sandbox: &Sandbox, target: &str, link: &str) {
+        std::os::unix::fs::symlink(sandbox.base.join(target), sandbox.workspace().join(link))
+            .unwrap();
+    }
+
+    #[test]
+    fn lexical_cases() {
+        #[rustfmt::skip]
+        let cases: &[(&str, &str, Expect)] = &[
+            ("existing file", "src/main.rs", Expect::Inside("src/main.rs")),
+            ("new file", "src/new.rs", Expect::Inside("src/new.rs")),
+            ("new nested dirs", "a/b/c.txt", Expect::Inside("a/b/c.txt")),
+            ("dot segments", "./src/./main.rs", Expect::Inside("src/main.rs")),
+            ("inner parent", "src/../lib.rs", Expect::Inside("lib.rs")),
+            ("missing dir then parent", "nope/../x.txt", Expect::Inside("x.txt")),
+            ("parent traversal", "../outside/secret.txt", Expect::Outside),
+            ("traversal to new file", "../../outside/new.txt", Expect::Outside),
+            ("traversal after subdir", "src/../../outside/new.txt", Expect::Outside),
+            ("traversal through missing dir", "nope/../../outside", Expect::Outside),
+            ("absolute outside", "/etc/passwd", Expect::Outside),
+            ("sibling prefix", "../workspace-evil/x.txt", Expect::Outside),
+            ("workspace itself", ".", Expect::Inside("")),
+        ];
+
+        let sandbox = Sandbox::new("lexical");
+        for (name, path, expect) in cases {
+            check(&sandbox, name, Path::new(path), expect);
+        }
+    }
+
+    #[test]
+    fn absolute_paths() {
+        let sandbox = Sandbox::new("absolute");
+        let inside = sandbox.workspace().join("src/main.rs");
+        check(
+            &sandbox,
+            "absolute inside",
+            &inside,
+            &Expect::Inside("src/main.rs"),
+        );
+
+        let canonical = sandbox.root().join("src/new.rs");
+        check(
+            &sandbox,
+            "canonical inside",
+            &canonical,
+            &Expect::Inside("src/new.rs"),
+        );
+
+        let outside = sandbox.base.join("outside/secret.txt");
+        check(&sandbox, "absolute outside", &outside, &Expect::Outside);
+
+        let sneaky = sandbox.workspace().join("../outside/new.txt");
+        check(
+            &sandbox,
+            "absolute with traversal",
+            &sneaky,
+            &Expect::Outside,
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cases() {
+        let sandbox = Sandbox::new("symlinks");
+        link(&sandbox, "outside", "out_dir");
+        link(&sandbox, "outside/secret.txt", "out_file");
+        link(&sandbox, "outside/missing.txt", "dangling");
+        link(&sandbox, "workspace/src", "src_link");
+        link(&sandbox, "workspace/out_dir", "chained");
+
+        #[rustfmt::skip]
+        let cases: &[(&str, &str, Expect)] = &[
+            ("dir link, existing file", "out_dir/secret.txt", Expect::SymlinkEscape),
+            ("dir link, new file", "out_dir/new.txt", Expect::SymlinkEscape),
+            ("dir link, new nested", "out_dir/a/b.txt", Expect::SymlinkEscape),
+            ("file link", "out_file", Expect::SymlinkEscape),
+            ("chained links", "chained/new.txt", Expect::SymlinkEscape),
+            ("dangling link", "dangling", Expect::Dangling),
+            ("internal link", "src_link/main.rs", Expect::Inside("src/main.rs")),
+            ("internal link, new file", "src_link/new.rs", Expect::Inside("src/new.rs")),
+            // Resolved lexically, so the link is never followed
+            ("link then parent", "out_dir/../x.txt", Expect::Inside("x.txt")),
+        ];
+
+        for (name, path, expect) in cases {
+            check(&sandbox, name, Path::new(path), expect);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_workspace() {
+        let sandbox = Sandbox::new("linked-root");
+        let alias = sandbox.base.join("alias");
+        std::os::unix::fs::symlink(sandbox.workspace(), &alias).unwrap();
+
+        let resolved = resolve(&alias, Path::new("src/new.rs")).unwrap();
+        assert_eq!(resolved, sandbox.root().join("src/new.rs"));
+
+        let escape = resolve(&alias, Path::new("../outside/new.txt"));
+        assert!(matches!(escape, Err(PathError::OutsideWorkspace(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lenient_canonicalization_matches_resolve() {
+        let sandbox = Sandbox::new("lenient");
+        let alias = sandbox.base.join("alias");
+        std::os::unix::fs::symlink(sandbox.workspace(), &alias).unwrap();
+
+        for path in [
+            "src/main.rs",
+            "src/new.rs",
+            "a/b/c.txt",
+            "nope/../x.txt",
+            ".",
+        ] {
+            assert_eq!(
+                canonicalize_lenient(&alias.join(path)),
+                resolve(&alias, Path::new(path)).unwrap(),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn missing_workspace() {
+        let sandbox = Sandbox::new("missing");
+        let result = resolve(&sandbox.base.join("gone"), Path::new("a.txt"));
+        assert!(matches!(result, Err(PathError::WorkspaceUnavailable(_))));
+    }
+
+    fn check(sandbox: &Sandbox, name: &str, path: &Path, expect: &Expect) {
+        let result = resolve(&sandbox.workspace(), path);
+        match (expect, &result) {
+            (Expect::Inside(rel), Ok(resolved)) => {
+                let expected = if rel.is_empty() {
+                    sandbox.root()
+                } else {
+                    sandbox.root().join(rel)
+                };
+                assert_eq!(resolved, &expected, "{name}");
+            }
+            (Expect::Outside, Err(PathError::OutsideWorkspace(_)))
+            | (Expect::SymlinkEscape, Err(PathError::SymlinkEscape { .. }))
+            | (Expect::Dangling, Err(PathError::DanglingSymlink(_))) => {}
+            _ => panic!("{name}: expected {expect:?}, got {result:?}"),
+        }
+    }
+}
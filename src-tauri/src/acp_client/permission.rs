@@ -0,0 +1,277 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use super::policy::PolicyAction;
+use crate::event_bus::{self, AgentEvent};
+use agent_client_protocol as acp;
+use tokio::sync::oneshot;
+
+struct PendingPermission {
+    session_id: String,
+    reply: oneshot::Sender<Option<acp::PermissionOptionId>>,
+}
+
+lazy_static::lazy_static! {
+    /// Permission requests waiting for an answer from the UI, keyed by request id.
+    static ref PENDING: Mutex<HashMap<String, PendingPermission>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Forwards a permission request to the UI and waits for the user's choice.
+/// Falls back to rejecting the tool call when nobody answers within `timeout`.
+/// `rule` is the approval rule that asked for a prompt, if any.
+pub async fn ask(
+    args: &acp::RequestPermissionRequest,
+    timeout: Duration,
+    rule: Option<String>,
+) -> acp::RequestPermissionOutcome {
+    let request_id = format!("perm-{}", NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed));
+
+    let (tx, rx) = oneshot::channel();
+    PENDING.lock().unwrap().insert(
+        request_id.clone(),
+        PendingPermission {
+            session_id: args.session_id.0.to_string(),
+            reply: tx,
+        },
+    );
+
+    event_bus::emit_event(AgentEvent::PermissionRequest {
+        session_id: args.session_id.0.to_string(),
+        request_id: request_id.clone(),
+        tool_call: Box::new(args.tool_call.clone()),
+        options: args.options.clone(),
+        timeout_secs: timeout.as_secs(),
+        rule: rule.clone(),
+    });
+
+    let (outcome, resolution) = match tokio::time::timeout(timeout, rx).await {
+        Ok(Ok(Some(option_id))) => {
+            if args
+                .options
+                .iter()
+                .any(|option| option.option_id == option_id)
+            {
+                (selected(option_id), "selected")
+            } else {
+                (reject(&args.options), "rejected")
+            }
+        }
+        Ok(Ok(None)) => (reject(&args.options), "rejected"),
+        // The sender was dropped by `cancel_session`
+        Ok(Err(_)) => (acp::RequestPermissionOutcome::Cancelled, "cancelled"),
+        Err(_) => {
+            PENDING.lock().unwrap().remove(&request_id);
+            (reject(&args.options), "timed_out")
+        }
+    };
+
+    emit_resolved(args, request_id, &outcome, resolution, rule);
+    outcome
+}
+
+/// Resolves every pending request of a session as cancelled.
+pub fn cancel_session(session_id: &str) {
+    // Dropping the senders wakes the waiting `ask` calls with an error.
+    PENDING
+        .lock()
+        .unwrap()
+        .retain(|_, pending| pending.session_id != session_id);
+}
+
+/// Answers a permission request on behalf of the user because an approval
+/// rule matched. Returns `None` when the agent offered no fitting option.
+pub fn resolve_by_rule(
+    args: &acp::RequestPermissionRequest,
+    action: PolicyAction,
+    rule: String,
+) -> Option<acp::RequestPermissionOutcome> {
+    let (outcome, resolution) = match action {
+        PolicyAction::Allow => (allow(&args.options)?, "auto_allowed"),
+        PolicyAction::Deny => (reject(&args.options), "auto_denied"),
+        PolicyAction::Ask => return None,
+    };
+
+    let request_id = format!("perm-{}", NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed));
+    emit_resolved(args, request_id, &outcome, resolution, Some(rule));
+    Some(outcome)
+}
+
+/// Whether the outcome selected one of the agent's "allow always" options.
+pub fn is_allow_always(
+    args: &acp::RequestPermissionRequest,
+    outcome: &acp::RequestPermissionOutcome,
+) -> bool {
+    let acp::RequestPermissionOutcome::Selected(selected) = outcome else {
+        return false;
+    };
+    args.options.iter().any(|option| {
+        option.option_id == selected.option_id
+            && matches!(option.kind, acp::PermissionOptionKind::AllowAlways)
+    })
+}
+
+fn emit_resolved(
+    args: &acp::RequestPermissionRequest,
+    request_id: String,
+    outcome: &acp::RequestPermissionOutcome,
+    resolution: &str,
+    rule: Option<String>,
+) {
+    let option_id = match outcome {
+        acp::RequestPermissionOutcome::Selected(selected) => Some(selected.option_id.0.to_string()),
+        _ => None,
+    };
+    event_bus::emit_event(AgentEvent::PermissionResolved {
+        session_id: args.session_id.0.to_string(),
+        request_id,
+        tool_call_id: args.tool_call.tool_call_id.0.to_string(),
+        title: args.tool_call.fields.title.clone(),
+        option_id,
+        resolution: resolution.to_string(),
+        rule,
+    });
+}
+
+/// Answers a pending permission request. `None` rejects the tool call.
+pub fn respond(request_id: &str, option_id: Option<String>) -> Result<(), String> {
+    let pending = PENDING
+        .lock()
+        .unwrap()
+        .remove(request_id)
+        .ok_or_else(|| format!("No pending permission request: {request_id}"))?;
+
+    pending
+        .reply
+        .send(option_id.map(acp::PermissionOptionId::new))
+        .map_err(|_| format!("Permission request {request_id} is no longer waiting"))
+}
+
+/// Picks the agent's "allow once" option, falling back to "allow always".
+fn allow(options: &[acp::PermissionOption]) -> Option<acp::RequestPermissionOutcome> {
+    options
+        .iter()
+        .find(|option| matches!(option.kind, acp::PermissionOptionKind::AllowOnce))
+        .or_else(|| {
+            options
+                .iter()
+                .find(|option| matches!(option.kind, acp::PermissionOptionKind::AllowAlways))
+        })
+        .map(|option| selected(option.option_id.clone()))
+}
+
+fn selected(option_id: acp::PermissionOptionId) -> acp::RequestPermissionOutcome {
+    acp::RequestPermissionOutcome::Selected(acp::SelectedPermissionOutcome::new(option_id))
+}
+
+/// Picks the agent's "reject once" option, or cancels if it offered none.
+fn reject(options: &[acp::PermissionOption]) -> acp::RequestPermissionOutcome {
+    options
+        .iter()
+        .find(|option| matches!(option.kind, acp::PermissionOptionKind::RejectOnce))
+        .or_else(|| {
+            options
+                .iter()
+                .find(|option| matches!(option.kind, acp::PermissionOptionKind::RejectAlways))
+        })
+        .map(|option| selected(option.option_id.clone()))
+        .unwrap_or(acp::RequestPermissionOutcome::Cancelled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(session_id: &str) -> acp::RequestPermissionRequest {
+        acp::RequestPermissionRequest::new(
+            session_id.to_string(),
+            acp::ToolCallUpdate::new("call-1", acp::ToolCallUpdateFields::new().title("Run")),
+            vec![
+                acp::PermissionOption::new("allow", "Allow", acp::PermissionOptionKind::AllowOnce),
+                acp::PermissionOption::new(
+                    "reject",
+                    "Reject",
+                    acp::PermissionOptionKind::RejectOnce,
+                ),
+            ],
+        )
+    }
+
+    fn pending_ids(session_id: &str) -> Vec<String> {
+        PENDING
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, pending)| pending.session_id == session_id)
+            .map(|(request_id, _)| request_id.clone())
+            .collect()
+    }
+
+    async fn wait_for_pending(session_id: &str, count: usize) -> Vec<String> {
+        for _ in 0..200 {
+            let ids = pending_ids(session_id);
+            if ids.len() == count {
+                return ids;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("{count} requests of {session_id} never became pending");
+    }
+
+    fn spawn_ask(session_id: &str) -> tokio::task::JoinHandle<acp::RequestPermissionOutcome> {
+        let args = request(session_id);
+        tokio::spawn(async move { ask(&args, Duration::from_secs(60), None).await })
+    }
+
+    fn selected_id(outcome: &acp::RequestPermissionOutcome) -> Option<String> {
+        match outcome {
+            acp::RequestPermissionOutcome::Selected(selected) => {
+                Some(selected.option_id.0.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn unanswered_requests_are_rejected_after_the_timeout() {
+        let args = request("permission-timeout");
+        let outcome = ask(&args, Duration::from_millis(50), None).await;
+        assert_eq!(selected_id(&outcome).as_deref(), Some("reject"));
+        assert!(pending_ids("permission-timeout").is_empty());
+
+        // Without a reject option the tool call is cancelled
+        let mut args = request("permission-timeout");
+        args.options
+            .retain(|option| option.option_id.0.as_ref() == "allow");
+        let outcome = ask(&args, Duration::from_millis(50), None).await;
+        assert!(matches!(outcome, acp::RequestPermissionOutcome::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_session_resolves_its_pending_requests() {
+        let first = spawn_ask("permission-cancel");
+        let second = spawn_ask("permission-cancel");
+        let other = spawn_ask("permission-other");
+        wait_for_pending("permission-cancel", 2).await;
+        let other_ids = wait_for_pending("permission-other", 1).await;
+
+        cancel_session("permission-cancel");
+        for outcome in [first.await.unwrap(), second.await.unwrap()] {
+            assert!(matches!(outcome, acp::RequestPermissionOutcome::Cancelled));
+        }
+        assert!(pending_ids("permission-cancel").is_empty());
+
+        // Other sessions keep waiting for the user
+        assert!(!other.is_finished());
+        respond(&other_ids[0], Some("allow".to_string())).unwrap();
+        assert_eq!(selected_id(&other.await.unwrap()).as_deref(), Some("allow"));
+        assert!(respond(&other_ids[0], None).is_err());
+    }
+}
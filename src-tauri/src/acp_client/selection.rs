@@ -0,0 +1,107 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use serde::Serialize;
+
+/// One of the options an agent lets a session choose from, e.g. a model
+#[derive(Debug, Clone, Serialize)]
+pub struct SelectionOption {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// The options of one kind a session chooses from, and its current choice
+pub trait Selection: Clone {
+    fn current(&self) -> &str;
+    fn select(&mut self, id: &str);
+}
+
+/// A selection per UI session id, for agents that report one
+pub struct SelectionStore<T> {
+    sessions: Mutex<HashMap<String, T>>,
+}
+
+impl<T: Selection> SelectionStore<T> {
+    pub fn new() -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records the selection returned by `new_session` or `load_session`.
+    pub fn set(&self, session_id: &str, selection: Option<impl Into<T>>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        match selection {
+            Some(selection) => {
+                sessions.insert(session_id.to_string(), selection.into());
+            }
+            None => {
+                sessions.remove(session_id);
+            }
+        }
+    }
+
+    pub fn set_current(&self, session_id: &str, id: &str) {
+        if let Some(selection) = self.sessions.lock().unwrap().get_mut(session_id) {
+            selection.select(id);
+        }
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<T> {
+        self.sessions.lock().unwrap().get(session_id).cloned()
+    }
+
+    pub fn current(&self, session_id: &str) -> Option<String> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .map(|selection| selection.current().to_string())
+    }
+
+    pub fn forget(&self, session_id: &str) {
+        self.sessions.lock().unwrap().remove(session_id);
+    }
+}
+
+impl<T: Selection> Default for SelectionStore<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct Choice(String);
+
+    impl Selection for Choice {
+        fn current(&self) -> &str {
+            &self.0
+        }
+
+        fn select(&mut self, id: &str) {
+            self.0 = id.to_string();
+        }
+    }
+
+    #[test]
+    fn selections_are_kept_per_session() {
+        let store = SelectionStore::<Choice>::new();
+        store.set("a", Some(Choice("fast".into())));
+        store.set("b", Some(Choice("slow".into())));
+        store.set_current("a", "smart");
+        store.set_current("missing", "smart");
+        assert_eq!(store.current("a").as_deref(), Some("smart"));
+        assert_eq!(store.current("b").as_deref(), Some("slow"));
+        assert!(store.get("missing").is_none());
+
+        // An agent that stopped reporting one
+        store.set("b", None::<Choice>);
+        assert!(store.current("b").is_none());
+        store.forget("a");
+        assert!(store.get("a").is_none());
+    }
+}
@@ -0,0 +1,323 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::Serialize;
+use similar::{DiffOp, TextDiff};
+
+use super::{
+    file_read::{self, LineRange},
+    file_versions, file_write,
+};
+use crate::{
+    event_bus::{self, AgentEvent},
+    file_watcher, settings,
+};
+
+/// Lines of context around each hunk
+const CONTEXT_LINES: usize = 3;
+
+/// A write held back for review
+#[derive(Debug, Clone)]
+struct StagedFile {
+    /// Content on disk when the write was staged; `None` for a new file
+    original: Option<String>,
+    content: String,
+}
+
+/// What the UI shows for a staged file
+#[derive(Debug, Clone, Serialize)]
+pub struct StagedDiff {
+    pub path: String,
+    pub is_new: bool,
+    /// Unified diff of the whole file
+    pub diff: String,
+    pub hunks: Vec<StagedHunk>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StagedHunk {
+    pub index: usize,
+    /// The `@@ -a,b +c,d @@` line
+    pub header: String,
+    pub diff: String,
+}
+
+lazy_static::lazy_static! {
+    /// Staged writes per session id, by absolute path
+    static ref STAGED: Mutex<HashMap<String, BTreeMap<PathBuf, StagedFile>>> = Mutex::new(HashMap::new());
+}
+
+/// Holds a write in the session's overlay instead of touching the disk.
+pub fn stage(session_id: &str, path: &Path, content: String) -> Result<(), String> {
+    let diff = {
+        let mut staged = STAGED.lock().unwrap();
+        let files = staged.entry(session_id.to_string()).or_default();
+        let original = match files.get(path) {
+            Some(file) => file.original.clone(),
+            None => read_original(path)?,
+        };
+        let file = StagedFile { original, content };
+        if file
+            .original
+            .as_deref()
+            .is_some_and(|original| lf(original) == lf(&file.content))
+        {
+            // Writing back what is on disk leaves nothing to review.
+            files.remove(path);
+            None
+        } else {
+            let diff = file.diff(path);
+            files.insert(path.to_path_buf(), file);
+            Some(diff)
+        }
+    };
+
+    emit_changed(session_id, path, diff);
+    Ok(())
+}
+
+/// Content the agent should see for `path`, if a write to it is staged.
+pub fn read(session_id: &str, path: &Path) -> Option<String> {
+    STAGED
+        .lock()
+        .unwrap()
+        .get(session_id)?
+        .get(path)
+        .map(|file| file.content.clone())
+}
+
+/// Diffs of every staged file of a session
+pub fn list(session_id: &str) -> Vec<StagedDiff> {
+    STAGED
+        .lock()
+        .unwrap()
+        .get(session_id)
+        .map(|files| files.iter().map(|(path, file)| file.diff(path)).collect())
+        .unwrap_or_default()
+}
+
+/// Writes the staged content of `path` to disk.
+pub fn accept(session_id: &str, path: &Path) -> Result<(), String> {
+    let file = staged_file(session_id, path)?;
+    check_unchanged(path, &file)?;
+    file_watcher::note_agent_write(session_id, path);
+    file_write::write(session_id, path, &file.content)?;
+    file_versions::record(session_id, path);
+    remove(session_id, path);
+    emit_changed(session_id, path, None);
+    Ok(())
+}
+
+/// Discards the staged content of `path`; the disk is left untouched.
+pub fn reject(session_id: &str, path: &Path) -> Result<(), String> {
+    staged_file(session_id, path)?;
+    remove(session_id, path);
+    emit_changed(session_id, path, None);
+    Ok(())
+}
+
+/// Writes a single hunk to disk and keeps the rest of the file staged.
+pub fn accept_hunk(session_id: &str, path: &Path, index: usize) -> Result<(), String> {
+    let file = staged_file(session_id, path)?;
+    check_unchanged(path, &file)?;
+
+    let original = lf(file.original.as_deref().unwrap_or_default());
+    let content = lf(&file.content);
+    let diff = TextDiff::from_lines(&original, &content);
+    let hunks = diff.grouped_ops(CONTEXT_LINES);
+    let hunk = hunks
+        .get(index)
+        .ok_or_else(|| format!("No hunk {index} staged for {}", path.display()))?;
+
+    // Take the new side of the hunk's changes and the old side everywhere else.
+    let selected: HashSet<_> = hunk.iter().filter_map(op_key).collect();
+    let mut merged = String::new();
+    for op in diff.ops() {
+        let lines = if op_key(op).is_some_and(|key| selected.contains(&key)) {
+            &diff.new_slices()[op.new_range()]
+        } else {
+            &diff.old_slices()[op.old_range()]
+        };
+        merged.extend(lines.iter().copied());
+    }
+
+    file_watcher::note_agent_write(session_id, path);
+    file_write::write(session_id, path, &merged)?;
+    file_versions::record(session_id, path);
+    // The write keeps the file's line endings, BOM and trailing newline, so
+    // what is on disk now may differ from `merged`.
+    let original = read_original(path)?;
+
+    let diff = {
+        let mut staged = STAGED.lock().unwrap();
+        let files = staged.entry(session_id.to_string()).or_default();
+        if merged == content {
+            files.remove(path);
+            None
+        } else {
+            let file = StagedFile {
+                original,
+                content: file.content,
+            };
+            let diff = file.diff(path);
+            files.insert(path.to_path_buf(), file);
+            Some(diff)
+        }
+    };
+    emit_changed(session_id, path, diff);
+    Ok(())
+}
+
+/// Drops everything staged for a session.
+pub fn forget(session_id: &str) {
+    STAGED.lock().unwrap().remove(session_id);
+}
+
+impl StagedFile {
+    fn diff(&self, path: &Path) -> StagedDiff {
+        let original = lf(self.original.as_deref().unwrap_or_default());
+        let content = lf(&self.content);
+        let diff = TextDiff::from_lines(&original, &content);
+        let name = path.display().to_string();
+        let old_header = if self.original.is_some() {
+            format!("a/{name}")
+        } else {
+            "/dev/null".to_string()
+        };
+
+        let mut unified = diff.unified_diff();
+        unified
+            .context_radius(CONTEXT_LINES)
+            .header(&old_header, &format!("b/{name}"));
+
+        let hunks = unified
+            .iter_hunks()
+            .enumerate()
+            .map(|(index, hunk)| StagedHunk {
+                index,
+                header: hunk.header().to_string(),
+                diff: hunk.to_string(),
+            })
+            .collect();
+
+        StagedDiff {
+            path: name,
+            is_new: self.original.is_none(),
+            diff: unified.to_string(),
+            hunks,
+        }
+    }
+}
+
+/// Line endings are kept by the write, so they are not part of the diff.
+fn lf(text: &str) -> Cow<'_, str> {
+    if text.contains("\r\n") {
+        Cow::Owned(text.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Identifies a changed op; equal ops are context and never selected.
+fn op_key(op: &DiffOp) -> Option<(usize, usize)> {
+    match op {
+        DiffOp::Equal { .. } => None,
+        _ => Some((op.old_range().start, op.new_range().start)),
+    }
+}
+
+fn staged_file(session_id: &str, path: &Path) -> Result<StagedFile, String> {
+    STAGED
+        .lock()
+        .unwrap()
+        .get(session_id)
+        .and_then(|files| files.get(path))
+        .cloned()
+        .ok_or_else(|| format!("No staged changes for {}", path.display()))
+}
+
+fn remove(session_id: &str, path: &Path) {
+    let mut staged = STAGED.lock().unwrap();
+    if let Some(files) = staged.get_mut(session_id) {
+        files.remove(path);
+        if files.is_empty() {
+            staged.remove(session_id);
+        }
+    }
+}
+
+fn read_original(path: &Path) -> Result<Option<String>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    file_read::read(
+        path,
+        LineRange::default(),
+        settings::current().max_read_bytes,
+    )
+    .map(Some)
+}
+
+/// Refuses to apply a change when the file moved on since it was staged.
+fn check_unchanged(path: &Path, file: &StagedFile) -> Result<(), String> {
+    if read_original(path)? != file.original {
+        return Err(format!(
+            "{} changed on disk since the write was staged",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+fn emit_changed(session_id: &str, path: &Path, diff: Option<StagedDiff>) {
+    event_bus::emit_event(AgentEvent::StagedChanged {
+        session_id: session_id.to_string(),
+        path: path.display().to_string(),
+        diff: diff.map(Box::new),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn accepts_hunks_one_after_another_on_crlf_files() {
+        let dir = std::env::temp_dir().join(format!("open-cowork-staging-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.txt");
+        let lines: Vec<String> = (1..=20).map(|n| format!("line {n}")).collect();
+        fs::write(&path, lines.join("\r\n") + "\r\n").unwrap();
+
+        let mut changed = lines.clone();
+        changed[1] = "second".to_string();
+        changed[17] = "eighteenth".to_string();
+        let session_id = "staging-test-crlf";
+        stage(session_id, &path, changed.join("\n") + "\n").unwrap();
+
+        let staged = list(session_id);
+        assert_eq!(staged[0].hunks.len(), 2, "{}", staged[0].diff);
+
+        accept_hunk(session_id, &path, 0).unwrap();
+        let on_disk = fs::read_to_string(&path).unwrap();
+        assert!(on_disk.contains("line 1\r\nsecond\r\nline 3\r\n"));
+        assert!(on_disk.contains("line 18\r\n"));
+        assert_eq!(list(session_id)[0].hunks.len(), 1);
+
+        accept_hunk(session_id, &path, 0).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            changed.join("\r\n") + "\r\n"
+        );
+        assert!(list(session_id).is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
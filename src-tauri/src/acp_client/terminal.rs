@@ -0,0 +1,410 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    process::{ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::event_bus::{self, AgentEvent};
+use crate::file_watcher;
+use agent_client_protocol as acp;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    process::Command,
+    sync::{watch, Notify},
+    time::Duration,
+};
+
+/// Output retained when the agent does not pass `output_byte_limit`.
+const DEFAULT_OUTPUT_BYTE_LIMIT: usize = 1024 * 1024;
+
+const PIPE_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Combined stdout/stderr of a terminal, truncated from the front once it
+/// grows past the byte limit.
+struct OutputBuffer {
+    text: String,
+    truncated: bool,
+    limit: usize,
+    /// Trailing bytes of an incomplete UTF-8 sequence from the last read.
+    pending: Vec<u8>,
+}
+
+impl OutputBuffer {
+    fn new(limit: usize) -> Self {
+        Self {
+            text: String::new(),
+            truncated: false,
+            limit,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Appends raw bytes and returns the newly decoded text.
+    fn push(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let decoded = match std::str::from_utf8(&self.pending) {
+            Ok(text) => {
+                let text = text.to_string();
+                self.pending.clear();
+                text
+            }
+            Err(err) if err.error_len().is_none() => {
+                // Incomplete sequence at the end: keep it for the next read.
+                let valid = err.valid_up_to();
+                let text = String::from_utf8_lossy(&self.pending[..valid]).to_string();
+                self.pending.drain(..valid);
+                text
+            }
+            Err(_) => String::from_utf8_lossy(&std::mem::take(&mut self.pending)).to_string(),
+        };
+
+        self.text.push_str(&decoded);
+        if self.text.len() > self.limit {
+            let mut cut = self.text.len() - self.limit;
+            while !self.text.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.text.drain(..cut);
+            self.truncated = true;
+        }
+        decoded
+    }
+}
+
+struct Terminal {
+    session_id: String,
+    output: Mutex<OutputBuffer>,
+    exit_status: watch::Sender<Option<acp::TerminalExitStatus>>,
+    kill: Notify,
+}
+
+/// Commands the agent runs through the ACP `terminal/*` methods.
+#[derive(Default)]
+pub struct TerminalManager {
+    terminals: Mutex<HashMap<String, Arc<Terminal>>>,
+    next_id: AtomicU64,
+}
+
+impl TerminalManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create(
+        &self,
+        args: acp::CreateTerminalRequest,
+        cwd: PathBuf,
+    ) -> Result<acp::TerminalId, String> {
+        let session_id = args.session_id.0.to_string();
+        let mut command = if args.args.is_empty() && args.command.contains(char::is_whitespace) {
+            // A bare command line, e.g. "cargo test --all"
+            shell_command(&args.command)
+        } else {
+            let mut command = Command::new(&args.command);
+            command.args(&args.args);
+            command
+        };
+        command
+            .current_dir(&cwd)
+            .envs(args.env.iter().map(|var| (&var.name, &var.value)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        // Its own process group, so killing it also stops what a shell started.
+        #[cfg(unix)]
+        command.process_group(0);
+
+        let mut child = command
+            .spawn()
+            .map_err(|err| format!("Failed to run `{}`: {err}", args.command))?;
+
+        let terminal_id = format!("term-{}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+        let limit = args
+            .output_byte_limit
+            .map(|limit| limit as usize)
+            .unwrap_or(DEFAULT_OUTPUT_BYTE_LIMIT);
+        let (exit_tx, _) = watch::channel(None);
+        let terminal = Arc::new(Terminal {
+            session_id: session_id.clone(),
+            output: Mutex::new(OutputBuffer::new(limit)),
+            exit_status: exit_tx,
+            kill: Notify::new(),
+        });
+        self.terminals
+            .lock()
+            .unwrap()
+            .insert(terminal_id.clone(), terminal.clone());
+
+        file_watcher::note_terminal_started(&session_id, &terminal_id, &cwd);
+        event_bus::emit_event(AgentEvent::TerminalStarted {
+            session_id,
+            terminal_id: terminal_id.clone(),
+            command: std::iter::once(args.command.as_str())
+                .chain(args.args.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" "),
+            cwd: cwd.to_string_lossy().to_string(),
+        });
+
+        let stdout = child
+            .stdout
+            .take()
+            .map(|stdout| tokio::spawn(pump_output(stdout, terminal.clone(), terminal_id.clone())));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|stderr| tokio::spawn(pump_output(stderr, terminal.clone(), terminal_id.clone())));
+
+        let waiter_id = terminal_id.clone();
+        let mut group = ProcessGroup {
+            leader: child.id(),
+            exited: false,
+        };
+        tokio::spawn(async move {
+            let status = tokio::select! {
+                status = child.wait() => status,
+                _ = terminal.kill.notified() => {
+                    group.kill();
+                    let _ = child.start_kill();
+                    child.wait().await
+                }
+            };
+            group.exited();
+            // Drain the pipes before reporting the exit so output is complete.
+            // Grandchildren that inherited the pipes may keep them open, so
+            // don't wait on them forever.
+            for pump in [stdout, stderr].into_iter().flatten() {
+                let _ = tokio::time::timeout(PIPE_DRAIN_TIMEOUT, pump).await;
+            }
+
+            let exit_status = match status {
+                Ok(status) => exit_status_of(status),
+                Err(_) => acp::TerminalExitStatus::new(),
+            };
+            file_watcher::note_terminal_exited(&waiter_id);
+            event_bus::emit_event(AgentEvent::TerminalExited {
+                session_id: terminal.session_id.clone(),
+                terminal_id: waiter_id,
+                exit_code: exit_status.exit_code,
+                signal: exit_status.signal.clone(),
+            });
+            terminal.exit_status.send_replace(Some(exit_status));
+        });
+
+        Ok(acp::TerminalId::new(terminal_id))
+    }
+
+    fn get(&self, terminal_id: &acp::TerminalId) -> Result<Arc<Terminal>, String> {
+        self.terminals
+            .lock()
+            .unwrap()
+            .get(terminal_id.0.as_ref())
+            .cloned()
+            .ok_or_else(|| format!("Unknown terminal: {}", terminal_id.0))
+    }
+
+    pub fn output(
+        &self,
+        terminal_id: &acp::TerminalId,
+    ) -> Result<acp::TerminalOutputResponse, String> {
+        let terminal = self.get(terminal_id)?;
+        let exit_status = terminal.exit_status.borrow().clone();
+        let output = terminal.output.lock().unwrap();
+        Ok(
+            acp::TerminalOutputResponse::new(output.text.clone(), output.truncated)
+                .exit_status(exit_status),
+        )
+    }
+
+    pub async fn wait_for_exit(
+        &self,
+        terminal_id: &acp::TerminalId,
+    ) -> Result<acp::TerminalExitStatus, String> {
+        let mut exit_status = self.get(terminal_id)?.exit_status.subscribe();
+        let status = exit_status
+            .wait_for(Option::is_some)
+            .await
+            .map_err(|_| format!("Terminal {} was dropped", terminal_id.0))?;
+        Ok(status.clone().unwrap_or_default())
+    }
+
+    /// Kills the command but keeps the terminal so its output stays readable.
+    pub fn kill(&self, terminal_id: &acp::TerminalId) -> Result<(), String> {
+        let terminal = self.get(terminal_id)?;
+        if terminal.exit_status.borrow().is_none() {
+            terminal.kill.notify_one();
+        }
+        Ok(())
+    }
+
+    /// Kills every running command started by a session.
+    pub fn kill_session(&self, session_id: &str) {
+        let terminals = self.terminals.lock().unwrap();
+        for terminal in terminals.values() {
+            if terminal.session_id == session_id && terminal.exit_status.borrow().is_none() {
+                terminal.kill.notify_one();
+            }
+        }
+    }
+
+    /// Kills the command if still running and forgets the terminal.
+    pub fn release(&self, terminal_id: &acp::TerminalId) -> Result<(), String> {
+        self.kill(terminal_id)?;
+        self.terminals
+            .lock()
+            .unwrap()
+            .remove(terminal_id.0.as_ref());
+        Ok(())
+    }
+}
+
+/// The process group a terminal's command leads. Killed when dropped before
+/// the command exited, e.g. on shutdown.
+struct ProcessGroup {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    leader: Option<u32>,
+    exited: bool,
+}
+
+impl ProcessGroup {
+    fn exited(&mut self) {
+        self.exited = true;
+    }
+
+    fn kill(&self) {
+        #[cfg(unix)]
+        if let Some(leader) = self.leader {
+            // SAFETY: killpg has no memory safety requirements.
+            unsafe {
+                libc::killpg(leader as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if !self.exited {
+            self.kill();
+        }
+    }
+}
+
+async fn pump_output<R: AsyncRead + Unpin>(
+    mut reader: R,
+    terminal: Arc<Terminal>,
+    terminal_id: String,
+) {
+    let mut buf = [0u8; 8192];
+    loop {
+        let read = match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+        let chunk = terminal.output.lock().unwrap().push(&buf[..read]);
+        if !chunk.is_empty() {
+            event_bus::emit_event(AgentEvent::TerminalOutput {
+                session_id: terminal.session_id.clone(),
+                terminal_id: terminal_id.clone(),
+                chunk,
+            });
+        }
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn shell_command(command_line: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(command_line);
+    command
+}
+
+#[cfg(windows)]
+pub(crate) fn shell_command(command_line: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(command_line);
+    command
+}
+
+fn exit_status_of(status: ExitStatus) -> acp::TerminalExitStatus {
+    #[cfg(unix)]
+    let signal = {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal().map(|signal| match signal {
+            2 => "SIGINT".to_string(),
+            9 => "SIGKILL".to_string(),
+            15 => "SIGTERM".to_string(),
+            other => format!("SIG{other}"),
+        })
+    };
+    #[cfg(not(unix))]
+    let signal: Option<String> = None;
+
+    acp::TerminalExitStatus::new()
+        .exit_code(status.code().map(|code| code as u32))
+        .signal(signal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_is_truncated_from_the_front_at_char_boundaries() {
+        let mut output = OutputBuffer::new(8);
+        assert_eq!(output.push(b"abc"), "abc");
+        assert!(!output.truncated);
+
+        output.push("d\u{e9}f\u{1f600}g".as_bytes());
+        assert!(output.truncated);
+        assert!(output.text.len() <= 8);
+        assert!("abcd\u{e9}f\u{1f600}g".ends_with(&output.text));
+        assert_eq!(output.text, "\u{e9}f\u{1f600}g");
+
+        // Cutting 1 byte would split the \u{e9}, so all of it goes.
+        output.push(b"h");
+        assert_eq!(output.text, "f\u{1f600}gh");
+    }
+
+    #[test]
+    fn split_utf8_sequences_wait_for_the_next_read() {
+        let mut output = OutputBuffer::new(DEFAULT_OUTPUT_BYTE_LIMIT);
+        let euro = "\u{20ac}".as_bytes();
+        assert_eq!(output.push(&euro[..1]), "");
+        assert_eq!(output.push(&euro[1..2]), "");
+        assert_eq!(output.push(&euro[2..]), "\u{20ac}");
+        assert_eq!(output.push(b"ok \xff!"), "ok \u{fffd}!");
+        assert_eq!(output.text, "\u{20ac}ok \u{fffd}!");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn kill_stops_the_whole_process_group() {
+        let manager = TerminalManager::new();
+        let request = acp::CreateTerminalRequest::new("terminal-test", "sleep 30 & echo $!; wait");
+        let terminal_id = manager.create(request, std::env::temp_dir()).unwrap();
+
+        let mut grandchild = None;
+        for _ in 0..100 {
+            let output = manager.output(&terminal_id).unwrap().output;
+            if let Ok(pid) = output.trim().parse::<u32>() {
+                grandchild = Some(pid);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let grandchild = grandchild.expect("the shell printed the background pid");
+
+        manager.kill(&terminal_id).unwrap();
+        manager.wait_for_exit(&terminal_id).await.unwrap();
+        // Gone, or a zombie waiting to be reaped by init
+        let stat = std::fs::read_to_string(format!("/proc/{grandchild}/stat")).unwrap_or_default();
+        let state = stat.rsplit(')').next().unwrap_or("").trim_start();
+        assert!(stat.is_empty() || state.starts_with('Z'), "{stat}");
+    }
+}
@@ -0,0 +1,423 @@
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+use agent_client_protocol as acp;
+use serde::Serialize;
+
+use super::client::content_text;
+use crate::event_bus::AgentEvent;
+
+/// A tool call with every update of the turn merged in
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolCallState {
+    pub tool_call_id: String,
+    pub title: String,
+    pub kind: acp::ToolKind,
+    pub status: acp::ToolCallStatus,
+    pub locations: Vec<acp::ToolCallLocation>,
+    pub content: Vec<ToolCallOutput>,
+    pub raw_input: Option<serde_json::Value>,
+    pub raw_output: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolCallOutput {
+    Text {
+        text: String,
+    },
+    Diff {
+        path: PathBuf,
+        old_text: Option<String>,
+        new_text: String,
+    },
+    Terminal {
+        terminal_id: String,
+    },
+}
+
+/// Tool calls and plan of a session's current or most recent turn
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TurnState {
+    /// In the order the agent started them
+    pub tool_calls: Vec<ToolCallState>,
+    pub plan: Vec<acp::PlanEntry>,
+    /// Set once the turn has finished
+    pub stop_reason: Option<String>,
+}
+
+/// Raw ACP state of a turn, kept to apply partial updates to
+#[derive(Default)]
+struct Turn {
+    tool_calls: Vec<acp::ToolCall>,
+    plan: Vec<acp::PlanEntry>,
+    stop_reason: Option<String>,
+}
+
+lazy_static::lazy_static! {
+    /// Turn state per UI session id
+    static ref TURNS: Mutex<HashMap<String, Turn>> = Mutex::new(HashMap::new());
+}
+
+/// Starts a new turn, dropping the state of the previous one.
+pub fn begin(session_id: &str) {
+    TURNS
+        .lock()
+        .unwrap()
+        .insert(session_id.to_string(), Turn::default());
+}
+
+pub fn finish(session_id: &str, stop_reason: &str) {
+    if let Some(turn) = TURNS.lock().unwrap().get_mut(session_id) {
+        turn.stop_reason = Some(stop_reason.to_string());
+    }
+}
+
+pub fn get(session_id: &str) -> TurnState {
+    let turns = TURNS.lock().unwrap();
+    let Some(turn) = turns.get(session_id) else {
+        return TurnState::default();
+    };
+    TurnState {
+        tool_calls: turn
+            .tool_calls
+            .iter()
+            .cloned()
+            .map(tool_call_state)
+            .collect(),
+        plan: turn.plan.clone(),
+        stop_reason: turn.stop_reason.clone(),
+    }
+}
+
+pub fn forget(session_id: &str) {
+    TURNS.lock().unwrap().remove(session_id);
+}
+
+/// Applies a session update with ACP's merge semantics and returns the
+/// normalized event for it, if it is one this module tracks.
+pub fn apply(session_id: &str, update: &acp::SessionUpdate) -> Option<AgentEvent> {
+    let mut turns = TURNS.lock().unwrap();
+    let turn = turns.entry(session_id.to_string()).or_default();
+
+    match update {
+        acp::SessionUpdate::ToolCall(tool_call) => {
+            let index = turn.position(&tool_call.tool_call_id);
+            match index {
+                Some(index) => turn.tool_calls[index] = tool_call.clone(),
+                None => turn.tool_calls.push(tool_call.clone()),
+            }
+            Some(AgentEvent::ToolCallChanged {
+                session_id: session_id.to_string(),
+                tool_call: Box::new(tool_call_state(tool_call.clone())),
+            })
+        }
+        acp::SessionUpdate::ToolCallUpdate(update) => {
+            let index = match turn.position(&update.tool_call_id) {
+                Some(index) => index,
+                None => {
+                    // The agent skipped the initial `tool_call`
+                    turn.tool_calls
+                        .push(acp::ToolCall::new(update.tool_call_id.clone(), ""));
+                    turn.tool_calls.len() - 1
+                }
+            };
+            let tool_call = &mut turn.tool_calls[index];
+            tool_call.update(update.fields.clone());
+            Some(AgentEvent::ToolCallChanged {
+                session_id: session_id.to_string(),
+                tool_call: Box::new(tool_call_state(tool_call.clone())),
+            })
+        }
+        acp::SessionUpdate::Plan(plan) => {
+            // Every plan update carries the complete list of entries.
+            turn.plan = plan.entries.clone();
+            Some(AgentEvent::PlanChanged {
+                session_id: session_id.to_string(),
+                entries: plan.entries.clone(),
+            })
+        }
+        _ => None,
+    }
+}
+
+impl Turn {
+    fn position(&self, tool_call_id: &acp::ToolCallId) -> Option<usize> {
+        self.tool_calls
+            .iter()
+            .position(|call| call.tool_call_id == *tool_call_id)
+    }
+}
+
+fn tool_call_state(tool_call: acp::ToolCall) -> ToolCallState {
+    ToolCallState {
+        tool_call_id: tool_call.tool_call_id.0.to_string(),
+        title: tool_call.title,
+        kind: tool_call.kind,
+        status: tool_call.status,
+        locations: tool_call.locations,
+        content: tool_call
+            .content
+            .into_iter()
+            .filter_map(|content| match content {
+                acp::ToolCallContent::Content(content) => Some(ToolCallOutput::Text {
+                    text: content_text(content.content),
+                }),
+                acp::ToolCallContent::Diff(diff) => Some(ToolCallOutput::Diff {
+                    path: diff.path,
+                    old_text: diff.old_text,
+                    new_text: diff.new_text,
+                }),
+                acp::ToolCallContent::Terminal(terminal) => Some(ToolCallOutput::Terminal {
+                    terminal_id: terminal.terminal_id.0.to_string(),
+                }),
+                _ => None,
+            })
+            .collect(),
+        raw_input: tool_call.raw_input,
+        raw_output: tool_call.raw_output,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(tool_call_id: &'static str, fields: acp::ToolCallUpdateFields) -> acp::SessionUpdate {
+        acp::SessionUpdate::ToolCallUpdate(acp::ToolCallUpdate::new(tool_call_id, fields))
+    }
+
+    fn only_tool_call(session_id: &str) -> ToolCallState {
+        let mut tool_calls = get(session_id).tool_calls;
+        assert_eq!(tool_calls.len(), 1);
+        tool_calls.remove(0)
+    }
+
+    #[test]
+    fn tool_call_updates_only_change_the_fields_they_carry() {
+        let session_id = "turn-state-partial";
+        begin(session_id);
+        apply(
+            session_id,
+            &acp::SessionUpdate::ToolCall(
+                acp::ToolCall::new("call-1", "Read file")
+                    .kind(acp::ToolKind::Read)
+                    .raw_input(serde_json::json!({ "path": "a.txt" })),
+            ),
+        );
+
+        let event = apply(
+            session_id,
+            &update(
+                "call-1",
+                acp::ToolCallUpdateFields::new().status(acp::ToolCallStatus::InProgress),
+            ),
+        );
+        let Some(AgentEvent::ToolCallChanged { tool_call, .. }) = event else {
+            panic!("expected a tool call event");
+        };
+        assert_eq!(tool_call.status, acp::ToolCallStatus::InProgress);
+
+        let tool_call = only_tool_call(session_id);
+        assert_eq!(tool_call.title, "Read file");
+        assert_eq!(tool_call.kind, acp::ToolKind::Read);
+        assert_eq!(
+            tool_call.raw_input,
+            Some(serde_json::json!({ "path": "a.txt" }))
+        );
+        assert_eq!(tool_call.raw_output, None);
+
+        apply(
+            session_id,
+            &update(
+                "call-1",
+                acp::ToolCallUpdateFields::new()
+                    .title("Read a.txt")
+                    .status(acp::ToolCallStatus::Completed)
+                    .raw_output(serde_json::json!("done")),
+            ),
+        );
+        let tool_call = only_tool_call(session_id);
+        assert_eq!(tool_call.title, "Read a.txt");
+        assert_eq!(tool_call.status, acp::ToolCallStatus::Completed);
+        assert_eq!(tool_call.kind, acp::ToolKind::Read);
+        assert_eq!(
+            tool_call.raw_input,
+            Some(serde_json::json!({ "path": "a.txt" }))
+        );
+        assert_eq!(tool_call.raw_output, Some(serde_json::json!("done")));
+        forget(session_id);
+    }
+
+    #[test]
+    fn content_and_locations_are_replaced_not_appended() {
+        let session_id = "turn-state-content";
+        begin(session_id);
+        apply(
+            session_id,
+            &acp::SessionUpdate::ToolCall(
+                acp::ToolCall::new("call-1", "Edit")
+                    .content(vec!["first".into()])
+                    .locations(vec![acp::ToolCallLocation::new("/w/a.txt")]),
+            ),
+        );
+
+        // Without content or locations, both are kept
+        apply(
+            session_id,
+            &update("call-1", acp::ToolCallUpdateFields::new().title("Edit a")),
+        );
+        let tool_call = only_tool_call(session_id);
+        assert!(matches!(
+            tool_call.content.as_slice(),
+            [ToolCallOutput::Text { text }] if text == "first"
+        ));
+        assert_eq!(tool_call.locations.len(), 1);
+
+        apply(
+            session_id,
+            &update(
+                "call-1",
+                acp::ToolCallUpdateFields::new()
+                    .content(vec![
+                        acp::Diff::new("/w/a.txt", "new").old_text("old").into(),
+                        acp::ToolCallContent::Terminal(acp::Terminal::new("term-1")),
+                    ])
+                    .locations(vec![
+                        acp::ToolCallLocation::new("/w/a.txt").line(3),
+                        acp::ToolCallLocation::new("/w/b.txt"),
+                    ]),
+            ),
+        );
+        let tool_call = only_tool_call(session_id);
+        match tool_call.content.as_slice() {
+            [ToolCallOutput::Diff {
+                path,
+                old_text,
+                new_text,
+            }, ToolCallOutput::Terminal { terminal_id }] => {
+                assert_eq!(path, &PathBuf::from("/w/a.txt"));
+                assert_eq!(old_text.as_deref(), Some("old"));
+                assert_eq!(new_text, "new");
+                assert_eq!(terminal_id, "term-1");
+            }
+            other => panic!("unexpected content {other:?}"),
+        }
+        let locations: Vec<_> = tool_call
+            .locations
+            .iter()
+            .map(|location| (location.path.clone(), location.line))
+            .collect();
+        assert_eq!(
+            locations,
+            [
+                (PathBuf::from("/w/a.txt"), Some(3)),
+                (PathBuf::from("/w/b.txt"), None)
+            ]
+        );
+
+        // An empty list clears them
+        apply(
+            session_id,
+            &update(
+                "call-1",
+                acp::ToolCallUpdateFields::new()
+                    .content(vec![])
+                    .locations(vec![]),
+            ),
+        );
+        let tool_call = only_tool_call(session_id);
+        assert!(tool_call.content.is_empty());
+        assert!(tool_call.locations.is_empty());
+        forget(session_id);
+    }
+
+    #[test]
+    fn tool_calls_keep_their_order_and_can_start_with_an_update() {
+        let session_id = "turn-state-order";
+        begin(session_id);
+        apply(
+            session_id,
+            &acp::SessionUpdate::ToolCall(acp::ToolCall::new("call-1", "One")),
+        );
+        // No `tool_call` was sent for this one
+        apply(
+            session_id,
+            &update(
+                "call-2",
+                acp::ToolCallUpdateFields::new().status(acp::ToolCallStatus::InProgress),
+            ),
+        );
+        // A repeated `tool_call` replaces the call in place
+        apply(
+            session_id,
+            &acp::SessionUpdate::ToolCall(
+                acp::ToolCall::new("call-1", "One again").status(acp::ToolCallStatus::Failed),
+            ),
+        );
+
+        let tool_calls = get(session_id).tool_calls;
+        let summary: Vec<_> = tool_calls
+            .iter()
+            .map(|call| (call.tool_call_id.as_str(), call.title.as_str(), call.status))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("call-1", "One again", acp::ToolCallStatus::Failed),
+                ("call-2", "", acp::ToolCallStatus::InProgress)
+            ]
+        );
+        forget(session_id);
+    }
+
+    #[test]
+    fn plans_are_replaced_and_turns_reset_the_state() {
+        let session_id = "turn-state-plan";
+        begin(session_id);
+        let entry = |content: &str, status| {
+            acp::PlanEntry::new(content, acp::PlanEntryPriority::Medium, status)
+        };
+        apply(
+            session_id,
+            &acp::SessionUpdate::Plan(acp::Plan::new(vec![
+                entry("Read", acp::PlanEntryStatus::InProgress),
+                entry("Write", acp::PlanEntryStatus::Pending),
+            ])),
+        );
+        let event = apply(
+            session_id,
+            &acp::SessionUpdate::Plan(acp::Plan::new(vec![entry(
+                "Write",
+                acp::PlanEntryStatus::InProgress,
+            )])),
+        );
+        assert!(
+            matches!(event, Some(AgentEvent::PlanChanged { entries, .. }) if entries.len() == 1)
+        );
+        let plan = get(session_id).plan;
+        assert_eq!(plan.len(), 1);
+        assert_eq!(plan[0].content, "Write");
+        assert_eq!(plan[0].status, acp::PlanEntryStatus::InProgress);
+
+        // Other updates are not tracked
+        assert!(apply(
+            session_id,
+            &acp::SessionUpdate::AgentMessageChunk(acp::ContentChunk::new("hi".into())),
+        )
+        .is_none());
+
+        apply(
+            session_id,
+            &acp::SessionUpdate::ToolCall(acp::ToolCall::new("call-1", "One")),
+        );
+        finish(session_id, "end_turn");
+        assert_eq!(get(session_id).stop_reason.as_deref(), Some("end_turn"));
+
+        begin(session_id);
+        let state = get(session_id);
+        assert!(state.tool_calls.is_empty() && state.plan.is_empty());
+        assert_eq!(state.stop_reason, None);
+        forget(session_id);
+        assert!(get(session_id).tool_calls.is_empty());
+    }
+}
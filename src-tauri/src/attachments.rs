@@ -0,0 +1,362 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use agent_client_protocol as acp;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Largest image that can be attached to a prompt
+pub const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+/// Largest file whose contents can be embedded in a prompt
+pub const MAX_FILE_BYTES: usize = 1024 * 1024;
+/// Limit for all attachments of one prompt together
+pub const MAX_TOTAL_BYTES: usize = 10 * 1024 * 1024;
+
+static ATTACHMENT_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the directory attachments are stored in. Called once during setup.
+pub fn init(dir: PathBuf) -> Result<(), String> {
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create attachment directory: {}", e))?;
+    let _ = ATTACHMENT_DIR.set(dir);
+    Ok(())
+}
+
+/// An attachment sent along with a prompt
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Attachment {
+    /// An image file, or base64 `data` such as a pasted screenshot
+    Image {
+        #[serde(default)]
+        path: Option<String>,
+        #[serde(default)]
+        data: Option<String>,
+        #[serde(default)]
+        mime_type: Option<String>,
+    },
+    /// A file whose contents are embedded in the prompt, or linked if the
+    /// agent doesn't accept embedded context
+    File { path: String },
+    /// A file or URL the agent reads itself
+    Link {
+        uri: String,
+        #[serde(default)]
+        name: Option<String>,
+    },
+}
+
+/// How an attachment is recorded in the transcript
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredAttachment {
+    pub kind: String, // "image", "file" or "link"
+    pub name: String,
+    pub mime_type: Option<String>,
+    pub size: Option<u64>,
+    /// SHA-256 of the content, which is stored under this name; `None` for links
+    pub hash: Option<String>,
+    pub uri: Option<String>,
+}
+
+/// Turns attachments into prompt content blocks. Checks them against the
+/// agent's prompt capabilities and the size limits, and stores a copy of
+/// their content.
+pub fn prepare(
+    attachments: Vec<Attachment>,
+    capabilities: &acp::PromptCapabilities,
+) -> Result<(Vec<acp::ContentBlock>, Vec<StoredAttachment>), String> {
+    let mut blocks = Vec::new();
+    let mut stored = Vec::new();
+    let mut total = 0;
+
+    for attachment in attachments {
+        let (block, record) = match attachment {
+            Attachment::Image {
+                path,
+                data,
+                mime_type,
+            } => {
+                if !capabilities.image {
+                    return Err("The agent does not accept images".into());
+                }
+                let (bytes, name) = match (path, data) {
+                    (Some(path), _) => (read_limited(Path::new(&path), MAX_IMAGE_BYTES)?, path),
+                    (None, Some(data)) => (
+                        BASE64
+                            .decode(data.trim())
+                            .map_err(|e| format!("Invalid image data: {}", e))?,
+                        "pasted image".to_string(),
+                    ),
+                    (None, None) => return Err("Image attachment has no path or data".into()),
+                };
+                check_size(&name, bytes.len(), MAX_IMAGE_BYTES)?;
+                let mime_type = match mime_type
+                    .or_else(|| sniff_image_mime_type(&bytes))
+                    .or_else(|| image_mime_type(Path::new(&name)))
+                {
+                    Some(mime_type) => mime_type,
+                    None => return Err(format!("Unsupported image type: {}", name)),
+                };
+                total += bytes.len();
+
+                let block = acp::ContentBlock::Image(acp::ImageContent::new(
+                    BASE64.encode(&bytes),
+                    mime_type.clone(),
+                ));
+                let record = StoredAttachment {
+                    kind: "image".into(),
+                    name,
+                    mime_type: Some(mime_type),
+                    size: Some(bytes.len() as u64),
+                    hash: Some(store(&bytes)?),
+                    uri: None,
+                };
+                (block, record)
+            }
+            Attachment::File { path } => {
+                let path = PathBuf::from(&path);
+                let uri = file_uri(&path)?;
+                let name = file_name(&path);
+
+                if !capabilities.embedded_context {
+                    // Every agent accepts links; it reads the file itself.
+                    let size = fs::metadata(&path)
+                        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+                        .len();
+                    let block = acp::ContentBlock::ResourceLink(
+                        acp::ResourceLink::new(name.clone(), uri.clone()).size(size as i64),
+                    );
+                    let record = StoredAttachment {
+                        kind: "link".into(),
+                        name,
+                        mime_type: None,
+                        size: Some(size),
+                        hash: None,
+                        uri: Some(uri),
+                    };
+                    (block, record)
+                } else {
+                    let bytes = read_limited(&path, MAX_FILE_BYTES)?;
+                    total += bytes.len();
+                    let hash = store(&bytes)?;
+                    let size = bytes.len() as u64;
+
+                    let (resource, mime_type) = match String::from_utf8(bytes) {
+                        Ok(text) => {
+                            let mime_type = text_mime_type(&path);
+                            (
+                                acp::EmbeddedResourceResource::TextResourceContents(
+                                    acp::TextResourceContents::new(text, uri.clone())
+                                        .mime_type(mime_type.clone()),
+                                ),
+                                mime_type,
+                            )
+                        }
+                        Err(err) => {
+                            let mime_type = "application/octet-stream".to_string();
+                            (
+                                acp::EmbeddedResourceResource::BlobResourceContents(
+                                    acp::BlobResourceContents::new(
+                                        BASE64.encode(err.into_bytes()),
+                                        uri.clone(),
+                                    )
+                                    .mime_type(mime_type.clone()),
+                                ),
+                                mime_type,
+                            )
+                        }
+                    };
+                    let block = acp::ContentBlock::Resource(acp::EmbeddedResource::new(resource));
+                    let record = StoredAttachment {
+                        kind: "file".into(),
+                        name,
+                        mime_type: Some(mime_type),
+                        size: Some(size),
+                        hash: Some(hash),
+                        uri: Some(uri),
+                    };
+                    (block, record)
+                }
+            }
+            Attachment::Link { uri, name } => {
+                let name = name.unwrap_or_else(|| uri.clone());
+                let block = acp::ContentBlock::ResourceLink(acp::ResourceLink::new(
+                    name.clone(),
+                    uri.clone(),
+                ));
+                let record = StoredAttachment {
+                    kind: "link".into(),
+                    name,
+                    mime_type: None,
+                    size: None,
+                    hash: None,
+                    uri: Some(uri),
+                };
+                (block, record)
+            }
+        };
+
+        if total > MAX_TOTAL_BYTES {
+            return Err(format!(
+                "Attachments exceed the limit of {} MiB per prompt",
+                MAX_TOTAL_BYTES / (1024 * 1024)
+            ));
+        }
+        blocks.push(block);
+        stored.push(record);
+    }
+
+    Ok((blocks, stored))
+}
+
+/// Reads a stored attachment by its hash.
+pub fn load(hash: &str) -> Result<Vec<u8>, String> {
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid attachment hash: {}", hash));
+    }
+    fs::read(attachment_dir()?.join(hash)).map_err(|e| format!("Failed to read attachment: {}", e))
+}
+
+fn attachment_dir() -> Result<&'static PathBuf, String> {
+    ATTACHMENT_DIR
+        .get()
+        .ok_or_else(|| "Attachment storage is not initialized".to_string())
+}
+
+/// Stores content under its SHA-256 hash, so identical attachments are kept once.
+fn store(bytes: &[u8]) -> Result<String, String> {
+    let hash = format!("{:x}", Sha256::digest(bytes));
+    let path = attachment_dir()?.join(&hash);
+    if !path.exists() {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, bytes)
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| format!("Failed to store attachment: {}", e))?;
+    }
+    Ok(hash)
+}
+
+fn read_limited(path: &Path, limit: usize) -> Result<Vec<u8>, String> {
+    let size = fs::metadata(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+        .len();
+    check_size(&path.display().to_string(), size as usize, limit)?;
+    fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+fn check_size(name: &str, size: usize, limit: usize) -> Result<(), String> {
+    if size > limit {
+        return Err(format!(
+            "{} is too large to attach ({} KiB, limit {} KiB)",
+            name,
+            size / 1024,
+            limit / 1024
+        ));
+    }
+    Ok(())
+}
+
+fn file_uri(path: &Path) -> Result<String, String> {
+    let path = path
+        .canonicalize()
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    url::Url::from_file_path(&path)
+        .map(String::from)
+        .map_err(|_| format!("Cannot link {}", path.display()))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+fn image_mime_type(path: &Path) -> Option<String> {
+    let mime_type = match extension(path).as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => return None,
+    };
+    Some(mime_type.to_string())
+}
+
+/// Recognizes the supported image formats by their magic bytes.
+fn sniff_image_mime_type(bytes: &[u8]) -> Option<String> {
+    let mime_type = if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        "image/jpeg"
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        "image/gif"
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        "image/webp"
+    } else {
+        return None;
+    };
+    Some(mime_type.to_string())
+}
+
+fn text_mime_type(path: &Path) -> String {
+    match extension(path).as_str() {
+        "md" | "markdown" => "text/markdown",
+        "json" => "application/json",
+        "html" | "htm" => "text/html",
+        "csv" => "text/csv",
+        "xml" => "application/xml",
+        _ => "text/plain",
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_image_types() {
+        let sniff = |bytes: &[u8]| sniff_image_mime_type(bytes);
+        assert_eq!(
+            sniff(b"\x89PNG\r\n\x1a\n\0\0").as_deref(),
+            Some("image/png")
+        );
+        assert_eq!(sniff(b"\xff\xd8\xff\xe0").as_deref(), Some("image/jpeg"));
+        assert_eq!(sniff(b"GIF89a\x01\0").as_deref(), Some("image/gif"));
+        assert_eq!(
+            sniff(b"RIFF\x24\0\0\0WEBPVP8 ").as_deref(),
+            Some("image/webp")
+        );
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WAVEfmt ").as_deref(), None);
+        assert_eq!(sniff(b"<svg></svg>").as_deref(), None);
+    }
+
+    #[test]
+    fn file_uris_are_percent_encoded() {
+        let dir =
+            std::env::temp_dir().join(format!("open-cowork-attachments-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes #1 100%.md");
+        fs::write(&path, "notes").unwrap();
+
+        let uri = file_uri(&path).unwrap();
+        assert!(uri.starts_with("file:///"), "{uri}");
+        assert!(uri.ends_with("/notes%20%231%20100%25.md"), "{uri}");
+        assert_eq!(
+            url::Url::parse(&uri).unwrap().to_file_path().unwrap(),
+            path.canonicalize().unwrap()
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
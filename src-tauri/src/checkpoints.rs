@@ -0,0 +1,362 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    event_bus::{self, AgentEvent},
+    transcript,
+};
+
+/// Longest prompt excerpt kept as a checkpoint's label
+const LABEL_CHARS: usize = 80;
+
+static CHECKPOINT_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the directory checkpoints are stored in. Called once during setup.
+pub fn init(dir: PathBuf) -> Result<(), String> {
+    for sub in ["sessions", "repos"] {
+        fs::create_dir_all(dir.join(sub))
+            .map_err(|e| format!("Failed to create checkpoint directory: {}", e))?;
+    }
+    let _ = CHECKPOINT_DIR.set(dir);
+    Ok(())
+}
+
+/// The state of the workspace before one turn of a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Commit in the workspace's shadow repository
+    pub id: String,
+    pub workspace: String,
+    pub label: String,
+    pub created_at: u64, // Unix timestamp in milliseconds
+    /// Files the turn added, changed or deleted; empty until the turn ends
+    pub files_changed: Vec<String>,
+    #[serde(default)]
+    pub line_counts: Vec<LineCount>,
+}
+
+/// Lines a turn added to and removed from one file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LineCount {
+    pub path: String,
+    /// `None` for binary files
+    pub added: Option<u64>,
+    pub removed: Option<u64>,
+}
+
+lazy_static::lazy_static! {
+    /// Checkpoint taken at the start of each session's running turn
+    static ref OPEN_TURNS: Mutex<HashMap<String, Checkpoint>> = Mutex::new(HashMap::new());
+    /// Serializes git commands per shadow repository
+    static ref REPO_LOCKS: Mutex<HashMap<PathBuf, Arc<Mutex<()>>>> = Mutex::new(HashMap::new());
+}
+
+/// Snapshots the workspace before a turn starts.
+pub async fn begin_turn(session_id: &str, workspace: &Path, prompt: &str) -> Result<(), String> {
+    let workspace = workspace
+        .canonicalize()
+        .map_err(|e| format!("Workspace is not accessible: {}", e))?;
+    let label: String = prompt.chars().take(LABEL_CHARS).collect();
+
+    let repo = ShadowRepo::open(&workspace)?;
+    let message = format!("before: {label}");
+    let id = blocking(move || {
+        let _guard = repo.lock.lock().unwrap();
+        repo.snapshot(&message)
+    })
+    .await?;
+
+    let checkpoint = Checkpoint {
+        id,
+        workspace: workspace.display().to_string(),
+        label,
+        created_at: now_millis(),
+        files_changed: Vec::new(),
+        line_counts: Vec::new(),
+    };
+    append(session_id, checkpoint.clone())?;
+    OPEN_TURNS
+        .lock()
+        .unwrap()
+        .insert(session_id.to_string(), checkpoint);
+    Ok(())
+}
+
+/// Records which files the session's turn changed, including changes made
+/// by terminal commands. Files that only other sessions changed meanwhile,
+/// as told by `foreign_files`, are left out. Returns `None` when no
+/// checkpoint was taken.
+pub async fn finish_turn(
+    session_id: &str,
+    foreign_files: impl FnOnce() -> HashSet<String>,
+) -> Result<Option<Checkpoint>, String> {
+    let Some(mut checkpoint) = OPEN_TURNS.lock().unwrap().remove(session_id) else {
+        return Ok(None);
+    };
+
+    let repo = ShadowRepo::open(Path::new(&checkpoint.workspace))?;
+    let before = checkpoint.id.clone();
+    let (files_changed, line_counts) = blocking(move || {
+        let _guard = repo.lock.lock().unwrap();
+        let after = repo.snapshot("after turn")?;
+        Ok((
+            repo.changed_files(&before, &after)?,
+            repo.line_counts(&before, &after)?,
+        ))
+    })
+    .await?;
+    // The shadow repository is shared by every session of the workspace.
+    let foreign = foreign_files();
+    checkpoint.files_changed = files_changed
+        .into_iter()
+        .filter(|path| !foreign.contains(path))
+        .collect();
+    checkpoint.line_counts = line_counts
+        .into_iter()
+        .filter(|count| !foreign.contains(&count.path))
+        .collect();
+
+    update(session_id, &checkpoint)?;
+    event_bus::emit_event(AgentEvent::CheckpointRecorded {
+        session_id: session_id.to_string(),
+        checkpoint: Box::new(checkpoint.clone()),
+    });
+    Ok(Some(checkpoint))
+}
+
+/// Drops the checkpoint of a turn that ended without finishing, e.g. because
+/// the agent process exited. The checkpoint stays in the session's list
+/// without its changed files.
+pub fn abandon_turn(session_id: &str) {
+    OPEN_TURNS.lock().unwrap().remove(session_id);
+}
+
+/// Checkpoints of a session, oldest first
+pub fn list(session_id: &str) -> Result<Vec<Checkpoint>, String> {
+    let path = index_path(session_id)?;
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid checkpoint index {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(format!("Failed to read checkpoints: {}", e)),
+    }
+}
+
+/// Rolls the workspace back to the state before the checkpoint's turn and
+/// returns the files that changed. The state being replaced is recorded as
+/// a new checkpoint first, so a restore can itself be undone. Refused while
+/// any session has a turn running in the workspace.
+pub async fn restore(session_id: &str, checkpoint_id: &str) -> Result<Vec<String>, String> {
+    let target = list(session_id)?
+        .into_iter()
+        .find(|checkpoint| checkpoint.id == checkpoint_id)
+        .ok_or_else(|| format!("No checkpoint {checkpoint_id} in this session"))?;
+
+    if OPEN_TURNS
+        .lock()
+        .unwrap()
+        .values()
+        .any(|open| open.workspace == target.workspace)
+    {
+        return Err(
+            "Cannot restore a checkpoint while a turn is running in the workspace".to_string(),
+        );
+    }
+
+    let repo = ShadowRepo::open(Path::new(&target.workspace))?;
+    let target_id = target.id.clone();
+    let (current, files) = blocking(move || {
+        let _guard = repo.lock.lock().unwrap();
+        let current = repo.snapshot(&format!("before restoring {target_id}"))?;
+        let files = repo.changed_files(&current, &target_id)?;
+        // Also removes files that did not exist yet at the checkpoint.
+        repo.git(&["read-tree", "-u", "--reset", &target_id])?;
+        Ok((current, files))
+    })
+    .await?;
+
+    append(
+        session_id,
+        Checkpoint {
+            id: current,
+            workspace: target.workspace,
+            label: format!("Before restoring \"{}\"", target.label),
+            created_at: now_millis(),
+            files_changed: files.clone(),
+            line_counts: Vec::new(),
+        },
+    )?;
+    Ok(files)
+}
+
+/// Deletes a session's checkpoint index. Snapshots stay in the shadow
+/// repository, which other sessions of the workspace share.
+pub fn delete(session_id: &str) -> Result<(), String> {
+    OPEN_TURNS.lock().unwrap().remove(session_id);
+    match fs::remove_file(index_path(session_id)?) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("Failed to delete checkpoints: {}", e)),
+    }
+}
+
+/// A git repository outside the workspace whose work tree is the workspace.
+/// Files ignored by the workspace's `.gitignore` are not captured.
+struct ShadowRepo {
+    git_dir: PathBuf,
+    workspace: PathBuf,
+    /// Held for the duration of every sequence of git commands
+    lock: Arc<Mutex<()>>,
+}
+
+impl ShadowRepo {
+    fn open(workspace: &Path) -> Result<Self, String> {
+        let dir = CHECKPOINT_DIR
+            .get()
+            .ok_or_else(|| "Checkpoint storage is not initialized".to_string())?;
+        let digest = Sha256::digest(workspace.as_os_str().as_encoded_bytes());
+        let name: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+        let git_dir = dir.join("repos").join(name);
+        let lock = REPO_LOCKS
+            .lock()
+            .unwrap()
+            .entry(git_dir.clone())
+            .or_default()
+            .clone();
+
+        Ok(Self {
+            git_dir,
+            workspace: workspace.to_path_buf(),
+            lock,
+        })
+    }
+
+    /// Commits the current state of the workspace and returns the commit id.
+    fn snapshot(&self, message: &str) -> Result<String, String> {
+        if !self.git_dir.join("HEAD").exists() {
+            self.git(&["init", "--quiet"])?;
+        }
+        self.git(&["add", "--all"])?;
+        self.git(&[
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "--no-verify",
+            "--message",
+            message,
+        ])?;
+        Ok(self.git(&["rev-parse", "HEAD"])?.trim().to_string())
+    }
+
+    fn changed_files(&self, from: &str, to: &str) -> Result<Vec<String>, String> {
+        let output = self.git(&["diff", "--name-only", "-z", "--no-renames", from, to])?;
+        Ok(output
+            .split('\0')
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn line_counts(&self, from: &str, to: &str) -> Result<Vec<LineCount>, String> {
+        let output = self.git(&["diff", "--numstat", "-z", "--no-renames", from, to])?;
+        // Each entry is "added\tremoved\tpath", with "-" counts for binary files.
+        Ok(output
+            .split('\0')
+            .filter_map(|entry| {
+                let mut fields = entry.splitn(3, '\t');
+                let added = fields.next()?;
+                let removed = fields.next()?;
+                Some(LineCount {
+                    path: fields.next()?.to_string(),
+                    added: added.parse().ok(),
+                    removed: removed.parse().ok(),
+                })
+            })
+            .collect())
+    }
+
+    fn git(&self, args: &[&str]) -> Result<String, String> {
+        let output = Command::new("git")
+            .arg("--git-dir")
+            .arg(&self.git_dir)
+            .arg("--work-tree")
+            .arg(&self.workspace)
+            .args([
+                "-c",
+                "user.name=Open Cowork",
+                "-c",
+                "user.email=checkpoints@open-cowork.local",
+                "-c",
+                "core.autocrlf=false",
+                "-c",
+                "commit.gpgsign=false",
+            ])
+            .args(args)
+            .env_remove("GIT_INDEX_FILE")
+            .current_dir(&self.workspace)
+            .output()
+            .map_err(|e| format!("Failed to run git: {}", e))?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "git {} failed: {}",
+                args.first().unwrap_or(&""),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| format!("Checkpoint task failed: {}", e))?
+}
+
+fn index_path(session_id: &str) -> Result<PathBuf, String> {
+    let dir = CHECKPOINT_DIR
+        .get()
+        .ok_or_else(|| "Checkpoint storage is not initialized".to_string())?;
+    Ok(dir
+        .join("sessions")
+        .join(format!("{}.json", transcript::file_stem(session_id))))
+}
+
+fn save(session_id: &str, checkpoints: &[Checkpoint]) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(checkpoints).map_err(|e| e.to_string())?;
+    fs::write(index_path(session_id)?, content)
+        .map_err(|e| format!("Failed to write checkpoints: {}", e))
+}
+
+fn append(session_id: &str, checkpoint: Checkpoint) -> Result<(), String> {
+    let mut checkpoints = list(session_id)?;
+    checkpoints.push(checkpoint);
+    save(session_id, &checkpoints)
+}
+
+fn update(session_id: &str, checkpoint: &Checkpoint) -> Result<(), String> {
+    let mut checkpoints = list(session_id)?;
+    match checkpoints.iter_mut().rev().find(|c| c.id == checkpoint.id) {
+        Some(existing) => *existing = checkpoint.clone(),
+        None => checkpoints.push(checkpoint.clone()),
+    }
+    save(session_id, &checkpoints)
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
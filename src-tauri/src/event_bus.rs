@@ -1,15 +1,54 @@
 use std::sync::Mutex;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use lazy_static::lazy_static;
-use agent_client_protocol::SessionUpdate;
+use agent_client_protocol::{PermissionOption, PlanEntry, SessionUpdate, ToolCallUpdate};
+use crate::attachments::StoredAttachment;
+use crate::checkpoints::Checkpoint;
+use crate::workspace_config::PrivilegedConfig;
+use crate::file_watcher::{ChangeKind, ChangeSource, FileChangeSummary};
+use crate::acp_client::{commands::SlashCommand, policy::ApprovalRule, staging::StagedDiff, turn_state::ToolCallState};
 
 #[derive(Clone, Debug, serde::Serialize)]
 #[serde(tag = "type", content = "payload")]
 pub enum AgentEvent {
     Status { session_id: String, status: String },
+    UserMessage { session_id: String, content: String, attachments: Vec<StoredAttachment> },
     Chunk { session_id: String, content: String },
     ThoughtChunk { session_id: String, content: String },
-    Update { session_id: String, update: SessionUpdate },
+    /// `replayed` is set for history `load_session` replays, which the transcript already has
+    Update { session_id: String, update: Box<SessionUpdate>, replayed: bool },
+    TurnComplete { session_id: String, stop_reason: String, model_id: Option<String> },
+    ToolCallChanged { session_id: String, tool_call: Box<ToolCallState> },
+    PlanChanged { session_id: String, entries: Vec<PlanEntry> },
+    /// A write was refused because the file changed since the agent read it
+    EditConflict { session_id: String, path: String, reason: String },
+    /// The agent wrote into an open editor buffer
+    BufferChanged { session_id: String, path: String, content: String, version: u64 },
+    CheckpointRecorded { session_id: String, checkpoint: Box<Checkpoint> },
+    /// `path` is relative to `workspace`
+    WorkspaceFileChanged { workspace: String, path: String, kind: ChangeKind, source: ChangeSource },
+    TurnFilesChanged { session_id: String, files: Vec<FileChangeSummary> },
+    /// `error` is set while `.open-cowork.toml` is invalid
+    WorkspaceConfigChanged { workspace: String, error: Option<String> },
+    /// The workspace's privileged configuration changed or was never approved;
+    /// `fingerprint` is passed back to approve it
+    WorkspaceApprovalRequired { workspace: String, config: Box<PrivilegedConfig>, fingerprint: String },
+    HookFinished { session_id: String, command: String, exit_code: Option<i32>, timed_out: bool, output: String },
+    /// `diff` is `None` once nothing is staged for the path any more
+    StagedChanged { session_id: String, path: String, diff: Option<Box<StagedDiff>> },
+    ModeChanged { session_id: String, mode_id: String },
+    ModelChanged { session_id: String, model_id: String },
+    CommandsAvailable { session_id: String, commands: Vec<SlashCommand> },
+    HistoryChunk { session_id: String, role: String, content: String },
+    SessionResumed { session_id: String, agent_session_id: String, restored: bool, reason: Option<String> },
+    TerminalStarted { session_id: String, terminal_id: String, command: String, cwd: String },
+    TerminalOutput { session_id: String, terminal_id: String, chunk: String },
+    TerminalExited { session_id: String, terminal_id: String, exit_code: Option<u32>, signal: Option<String> },
+    PermissionRequest { session_id: String, request_id: String, tool_call: Box<ToolCallUpdate>, options: Vec<PermissionOption>, timeout_secs: u64, rule: Option<String> },
+    PermissionResolved { session_id: String, request_id: String, tool_call_id: String, title: Option<String>, option_id: Option<String>, resolution: String, rule: Option<String> },
+    ApprovalGranted { session_id: String, workspace: String, rule: ApprovalRule },
+    AgentExited { provider_id: String, session_ids: Vec<String>, exit_code: Option<i32>, signal: Option<i32>, stderr_tail: Vec<String>, restart_in_secs: Option<u64> },
+    AgentRestarted { provider_id: String, session_ids: Vec<String>, reattached: Vec<String> },
 }
 
 lazy_static! {
@@ -0,0 +1,445 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+use notify::{event::ModifyKind, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+
+use crate::{
+    checkpoints::LineCount,
+    event_bus::{self, AgentEvent},
+    workspace_config,
+};
+
+/// How long after an agent write, or after a terminal or hook exited,
+/// changes are still attributed to it
+const ATTRIBUTION_WINDOW: Duration = Duration::from_secs(2);
+/// Repeated events of one kind for the same path and source within this
+/// window are reported once
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// What made a change to a workspace file
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChangeSource {
+    /// The session's agent through `write_text_file`
+    Agent { session_id: String },
+    /// A command the session's agent runs in a terminal
+    Terminal {
+        session_id: String,
+        terminal_id: String,
+    },
+    /// A post-turn hook of the workspace configuration
+    Hook { session_id: String, command: String },
+    /// Anything else, e.g. the user's editor
+    External,
+}
+
+impl ChangeSource {
+    /// The session behind the change; `None` for external changes
+    fn session_id(&self) -> Option<&str> {
+        match self {
+            ChangeSource::Agent { session_id }
+            | ChangeSource::Terminal { session_id, .. }
+            | ChangeSource::Hook { session_id, .. } => Some(session_id),
+            ChangeSource::External => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+/// A file one turn changed
+#[derive(Debug, Clone, Serialize)]
+pub struct FileChangeSummary {
+    pub path: String,
+    /// `None` for binary files, or when no checkpoint was taken
+    pub lines_added: Option<u64>,
+    pub lines_removed: Option<u64>,
+    /// Empty when the watcher missed the change
+    pub sources: Vec<ChangeSource>,
+}
+
+/// A terminal command or hook whose changes are attributed to it
+struct ProcessRun {
+    session_id: String,
+    source: ChangeSource,
+    /// Only changes below this directory are attributed to the process
+    cwd: PathBuf,
+    started: Instant,
+    exited: Option<Instant>,
+    /// Still running after the turn that started it ended, e.g. a dev server.
+    /// Its changes can't be told apart from the user's own edits.
+    outlived_turn: bool,
+}
+
+#[derive(Default)]
+struct Attribution {
+    /// Sessions using each watched workspace
+    workspaces: HashMap<PathBuf, HashSet<String>>,
+    /// Files the agents are about to write, by absolute path
+    agent_writes: HashMap<PathBuf, (String, Instant)>,
+    /// By terminal id, or session and command of a hook
+    processes: HashMap<String, ProcessRun>,
+    /// Changed files and their sources during each session's running turn
+    turns: HashMap<String, BTreeMap<String, BTreeSet<ChangeSource>>>,
+    last_emitted: HashMap<(PathBuf, ChangeKind, ChangeSource), Instant>,
+}
+
+lazy_static::lazy_static! {
+    /// One watcher per workspace, dropped with its last session
+    static ref WATCHERS: Mutex<HashMap<PathBuf, RecommendedWatcher>> = Mutex::new(HashMap::new());
+    static ref ATTRIBUTION: Mutex<Attribution> = Mutex::new(Attribution::default());
+}
+
+/// Starts reporting changes in the session's workspace, sharing the watcher
+/// with other sessions of the same workspace.
+pub fn watch(session_id: &str, workspace: &Path) -> Result<(), String> {
+    let workspace = workspace
+        .canonicalize()
+        .map_err(|e| format!("Workspace is not accessible: {}", e))?;
+    let mut watchers = WATCHERS.lock().unwrap();
+    if !watchers.contains_key(&workspace) {
+        let mut ignored = Ignored::new(&workspace);
+        let root = workspace.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if let Ok(event) = event {
+                    handle(&root, &mut ignored, event);
+                }
+            })
+            .map_err(|e| format!("Failed to watch workspace: {}", e))?;
+        watcher
+            .watch(&workspace, RecursiveMode::Recursive)
+            .map_err(|e| format!("Failed to watch workspace: {}", e))?;
+        watchers.insert(workspace.clone(), watcher);
+    }
+
+    ATTRIBUTION
+        .lock()
+        .unwrap()
+        .workspaces
+        .entry(workspace)
+        .or_default()
+        .insert(session_id.to_string());
+    Ok(())
+}
+
+/// Stops watching for the session and drops watchers no session uses.
+pub fn unwatch(session_id: &str) {
+    let unused: Vec<PathBuf> = {
+        let mut attribution = ATTRIBUTION.lock().unwrap();
+        attribution.turns.remove(session_id);
+        let mut unused = Vec::new();
+        attribution.workspaces.retain(|workspace, sessions| {
+            sessions.remove(session_id);
+            if sessions.is_empty() {
+                unused.push(workspace.clone());
+            }
+            !sessions.is_empty()
+        });
+        unused
+    };
+    // Dropping a watcher waits for its event thread, which may be waiting
+    // for the attribution lock.
+    let mut watchers = WATCHERS.lock().unwrap();
+    for workspace in unused {
+        let reused = ATTRIBUTION
+            .lock()
+            .unwrap()
+            .workspaces
+            .contains_key(&workspace);
+        if !reused {
+            watchers.remove(&workspace);
+        }
+    }
+}
+
+/// Attributes the next changes of `path` to the session's agent. Called
+/// right before the file is written.
+pub fn note_agent_write(session_id: &str, path: &Path) {
+    ATTRIBUTION
+        .lock()
+        .unwrap()
+        .agent_writes
+        .insert(path.to_path_buf(), (session_id.to_string(), Instant::now()));
+}
+
+pub fn note_terminal_started(session_id: &str, terminal_id: &str, cwd: &Path) {
+    let source = ChangeSource::Terminal {
+        session_id: session_id.to_string(),
+        terminal_id: terminal_id.to_string(),
+    };
+    note_process_started(session_id, terminal_id, cwd, source);
+}
+
+pub fn note_terminal_exited(terminal_id: &str) {
+    note_process_exited(terminal_id);
+}
+
+pub fn note_hook_started(session_id: &str, command: &str, cwd: &Path) {
+    let source = ChangeSource::Hook {
+        session_id: session_id.to_string(),
+        command: command.to_string(),
+    };
+    note_process_started(session_id, &hook_key(session_id, command), cwd, source);
+}
+
+pub fn note_hook_exited(session_id: &str, command: &str) {
+    note_process_exited(&hook_key(session_id, command));
+}
+
+fn hook_key(session_id: &str, command: &str) -> String {
+    format!("hook:{session_id}:{command}")
+}
+
+fn note_process_started(session_id: &str, id: &str, cwd: &Path, source: ChangeSource) {
+    // Event paths are canonical.
+    let cwd = cwd.canonicalize().unwrap_or_else(|_| cwd.to_path_buf());
+    ATTRIBUTION.lock().unwrap().processes.insert(
+        id.to_string(),
+        ProcessRun {
+            session_id: session_id.to_string(),
+            source,
+            cwd,
+            started: Instant::now(),
+            exited: None,
+            outlived_turn: false,
+        },
+    );
+}
+
+fn note_process_exited(id: &str) {
+    if let Some(run) = ATTRIBUTION.lock().unwrap().processes.get_mut(id) {
+        run.exited = Some(Instant::now());
+    }
+}
+
+/// Starts collecting the files changed during the session's turn.
+pub fn begin_turn(session_id: &str) {
+    ATTRIBUTION
+        .lock()
+        .unwrap()
+        .turns
+        .insert(session_id.to_string(), BTreeMap::new());
+}
+
+/// Files changed during the session's running turn that only other sessions'
+/// agents, terminals or hooks changed
+pub fn foreign_files(session_id: &str) -> HashSet<String> {
+    let attribution = ATTRIBUTION.lock().unwrap();
+    let Some(files) = attribution.turns.get(session_id) else {
+        return HashSet::new();
+    };
+    files
+        .iter()
+        .filter(|(_, sources)| {
+            sources
+                .iter()
+                .all(|source| source.session_id().is_some_and(|other| other != session_id))
+        })
+        .map(|(path, _)| path.clone())
+        .collect()
+}
+
+/// Ends the turn and summarizes the files it changed. Line counts come from
+/// the turn's checkpoint when there is one; otherwise every file the watcher
+/// saw is listed.
+pub fn finish_turn(session_id: &str, line_counts: Option<&[LineCount]>) -> Vec<FileChangeSummary> {
+    let mut attribution = ATTRIBUTION.lock().unwrap();
+    for run in attribution.processes.values_mut() {
+        if run.session_id == session_id && run.exited.is_none() {
+            run.outlived_turn = true;
+        }
+    }
+    let mut sources = attribution.turns.remove(session_id).unwrap_or_default();
+    drop(attribution);
+
+    match line_counts {
+        Some(counts) => counts
+            .iter()
+            .map(|count| FileChangeSummary {
+                path: count.path.clone(),
+                lines_added: count.added,
+                lines_removed: count.removed,
+                sources: sources
+                    .remove(&count.path)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect(),
+            })
+            .collect(),
+        None => sources
+            .into_iter()
+            .map(|(path, sources)| FileChangeSummary {
+                path,
+                lines_added: None,
+                lines_removed: None,
+                sources: sources.into_iter().collect(),
+            })
+            .collect(),
+    }
+}
+
+/// The `.gitignore` files of the workspace and its subdirectories, honored
+/// the way git, and so the checkpoints, honor them. Each file is read when
+/// first needed and again after it changed.
+struct Ignored {
+    root: PathBuf,
+    /// By directory relative to the root
+    files: HashMap<PathBuf, Gitignore>,
+}
+
+impl Ignored {
+    fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            files: HashMap::new(),
+        }
+    }
+
+    /// The deepest `.gitignore` with a matching pattern decides.
+    fn is_ignored(&mut self, relative: &Path, is_dir: bool) -> bool {
+        let path = self.root.join(relative);
+        for dir in relative.ancestors().skip(1) {
+            let root = &self.root;
+            let gitignore = self.files.entry(dir.to_path_buf()).or_insert_with(|| {
+                let dir = root.join(dir);
+                let mut builder = GitignoreBuilder::new(&dir);
+                builder.add(dir.join(".gitignore"));
+                builder.build().unwrap_or_else(|_| Gitignore::empty())
+            });
+            match gitignore.matched_path_or_any_parents(&path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+
+    fn changed(&mut self, gitignore: &Path) {
+        if let Some(dir) = gitignore.parent() {
+            self.files.remove(dir);
+        }
+    }
+}
+
+fn handle(workspace: &Path, ignored: &mut Ignored, event: notify::Event) {
+    let kind = match event.kind {
+        EventKind::Create(_) => ChangeKind::Created,
+        EventKind::Remove(_) => ChangeKind::Removed,
+        EventKind::Modify(ModifyKind::Metadata(_)) => return,
+        EventKind::Modify(_) => ChangeKind::Modified,
+        _ => return,
+    };
+
+    for path in event.paths {
+        let Ok(relative) = path.strip_prefix(workspace) else {
+            continue;
+        };
+        if relative == Path::new(workspace_config::CONFIG_FILE_NAME) {
+            workspace_config::reload(workspace);
+        }
+        if relative.file_name() == Some(".gitignore".as_ref()) {
+            ignored.changed(relative);
+        }
+        if is_internal(relative) || ignored.is_ignored(relative, path.is_dir()) {
+            continue;
+        }
+        // A rename reports both names; the one that is gone was removed.
+        let kind = match kind {
+            ChangeKind::Modified if !path.exists() => ChangeKind::Removed,
+            kind => kind,
+        };
+        record(workspace, &path, relative, kind);
+    }
+}
+
+/// Version control data and the temporary files of atomic writes
+fn is_internal(relative: &Path) -> bool {
+    if relative
+        .components()
+        .any(|component| component == Component::Normal(".git".as_ref()))
+    {
+        return true;
+    }
+    let name = relative.file_name().unwrap_or_default().to_string_lossy();
+    name.starts_with('.') && name.ends_with(".tmp")
+}
+
+fn record(workspace: &Path, path: &Path, relative: &Path, kind: ChangeKind) {
+    let now = Instant::now();
+    let mut attribution = ATTRIBUTION.lock().unwrap();
+    let sessions = attribution
+        .workspaces
+        .get(workspace)
+        .cloned()
+        .unwrap_or_default();
+
+    attribution
+        .agent_writes
+        .retain(|_, (_, at)| now.duration_since(*at) < ATTRIBUTION_WINDOW);
+    attribution.processes.retain(|_, run| {
+        run.exited
+            .is_none_or(|exited| now.duration_since(exited) < ATTRIBUTION_WINDOW)
+    });
+    attribution
+        .last_emitted
+        .retain(|_, at| now.duration_since(*at) < DEBOUNCE);
+
+    let source = if let Some((session_id, _)) = attribution.agent_writes.get(path) {
+        ChangeSource::Agent {
+            session_id: session_id.clone(),
+        }
+    } else if let Some(run) = attribution
+        .processes
+        .values()
+        .filter(|run| {
+            sessions.contains(&run.session_id)
+                && path.starts_with(&run.cwd)
+                && (run.exited.is_some() || !run.outlived_turn)
+        })
+        .max_by_key(|run| run.started)
+    {
+        run.source.clone()
+    } else {
+        ChangeSource::External
+    };
+
+    let relative = relative.to_string_lossy().replace('\\', "/");
+    for session_id in &sessions {
+        if let Some(files) = attribution.turns.get_mut(session_id) {
+            files
+                .entry(relative.clone())
+                .or_default()
+                .insert(source.clone());
+        }
+    }
+
+    let key = (path.to_path_buf(), kind, source.clone());
+    if attribution.last_emitted.insert(key, now).is_some() {
+        return;
+    }
+    drop(attribution);
+
+    event_bus::emit_event(AgentEvent::WorkspaceFileChanged {
+        workspace: workspace.display().to_string(),
+        path: relative,
+        kind,
+        source,
+    });
+}
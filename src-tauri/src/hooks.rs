@@ -0,0 +1,103 @@
+use std::{collections::BTreeMap, path::Path, process::Stdio, time::Duration};
+
+use crate::{
+    acp_client::terminal::shell_command,
+    event_bus::{self, AgentEvent},
+    file_watcher,
+    workspace_config::{self, Hook},
+};
+
+/// Output kept per hook for the UI
+const OUTPUT_TAIL_BYTES: usize = 4096;
+
+/// Runs the workspace's post-turn hooks one after another, once the user
+/// approved them. Failures are reported to the UI and don't stop the
+/// remaining hooks.
+pub async fn run_post_turn(session_id: &str, workspace: &Path) {
+    let config = match workspace_config::load(workspace) {
+        Ok(config) => config,
+        Err(err) => {
+            event_bus::emit_event(AgentEvent::Status {
+                session_id: session_id.to_string(),
+                status: format!("Post-turn hooks skipped: {err}"),
+            });
+            return;
+        }
+    };
+    if !workspace_config::request_approval(workspace, &config) {
+        event_bus::emit_event(AgentEvent::Status {
+            session_id: session_id.to_string(),
+            status: "Post-turn hooks skipped: waiting for approval".to_string(),
+        });
+        return;
+    }
+
+    for hook in &config.hooks.post_turn {
+        event_bus::emit_event(AgentEvent::Status {
+            session_id: session_id.to_string(),
+            status: format!("Running hook: {}", hook.command),
+        });
+        file_watcher::note_hook_started(session_id, &hook.command, workspace);
+        let result = run(hook, workspace, &config.env).await;
+        file_watcher::note_hook_exited(session_id, &hook.command);
+
+        let (exit_code, timed_out, output) = match result {
+            Ok(HookOutput::Exited { code, output }) => (code, false, output),
+            Ok(HookOutput::TimedOut) => (None, true, String::new()),
+            Err(err) => (None, false, err),
+        };
+        event_bus::emit_event(AgentEvent::HookFinished {
+            session_id: session_id.to_string(),
+            command: hook.command.clone(),
+            exit_code,
+            timed_out,
+            output,
+        });
+    }
+}
+
+enum HookOutput {
+    Exited { code: Option<i32>, output: String },
+    TimedOut,
+}
+
+async fn run(
+    hook: &Hook,
+    workspace: &Path,
+    env: &BTreeMap<String, String>,
+) -> Result<HookOutput, String> {
+    let child = shell_command(&hook.command)
+        .current_dir(workspace)
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| format!("Failed to run `{}`: {err}", hook.command))?;
+
+    let output = match tokio::time::timeout(
+        Duration::from_secs(hook.timeout_secs),
+        child.wait_with_output(),
+    )
+    .await
+    {
+        // Dropping the future kills the hook.
+        Err(_) => return Ok(HookOutput::TimedOut),
+        Ok(output) => output.map_err(|err| format!("Failed to run `{}`: {err}", hook.command))?,
+    };
+
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    if text.len() > OUTPUT_TAIL_BYTES {
+        let mut cut = text.len() - OUTPUT_TAIL_BYTES;
+        while !text.is_char_boundary(cut) {
+            cut += 1;
+        }
+        text.drain(..cut);
+    }
+    Ok(HookOutput::Exited {
+        code: output.status.code(),
+        output: text,
+    })
+}
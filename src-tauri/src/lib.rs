@@ -1,11 +1,28 @@
 mod acp_agent_provider;
 mod acp_client;
+mod attachments;
+mod checkpoints;
 mod event_bus;
+mod file_watcher;
+mod hooks;
 mod session_store;
+mod settings;
+mod transcript;
+mod workspace_config;
 
-use acp_agent_provider::codex;
-use session_store::{SessionMetadata, SESSION_STORE_KEY};
-use tauri::Emitter;
+use acp_agent_provider::{worker::{self, ResumeInfo}, ProviderInfo};
+use attachments::Attachment;
+use checkpoints::Checkpoint;
+use acp_client::{buffers, commands::{self, CommandInvocation, SlashCommand}, models::{self, SessionModels}, modes::{self, SessionModes}, file_versions, file_write, permission, policy::{self, ApprovalRule}, staging::{self, StagedDiff}, turn_state::{self, TurnState}};
+use session_store::{
+    PermissionDecision, SessionMetadata, APPROVAL_STORE_KEY, WORKSPACE_APPROVAL_STORE_KEY, PERMISSION_STORE_KEY,
+    SESSION_STORE_KEY,
+};
+use std::collections::HashMap;
+use std::path::Path;
+use settings::{Settings, SETTINGS_STORE_KEY};
+use tauri::{Emitter, Manager};
+use transcript::{TranscriptEntry, TranscriptPage};
 use tauri_plugin_store::StoreExt;
 use std::sync::Mutex;
 
@@ -21,13 +38,117 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-async fn send_agent_message(message: String, session_id: Option<String>) -> Result<String, String> {
-    codex::send_codex_message(message, session_id).await
+async fn send_agent_message(
+    app: tauri::AppHandle,
+    message: String,
+    session_id: Option<String>,
+    command: Option<CommandInvocation>,
+    attachments: Option<Vec<Attachment>>,
+) -> Result<String, String> {
+    let stored = match &session_id {
+        Some(id) => stored_session(&app, id)?,
+        None => None,
+    };
+
+    // A slash command picked from autocomplete replaces the message text
+    let message = match (&command, &session_id) {
+        (Some(command), Some(id)) => commands::prompt_text(id, command)?,
+        (Some(_), None) => return Err("Slash commands need a session".into()),
+        (None, _) => message,
+    };
+
+    worker::send_message(
+        message,
+        session_id,
+        resume_info(stored),
+        attachments.unwrap_or_default(),
+    )
+    .await
+}
+
+/// Looks up a persisted session and binds it to its provider; its workspace,
+/// mode and model are needed to resume it.
+fn stored_session(
+    app: &tauri::AppHandle,
+    session_id: &str,
+) -> Result<Option<SessionMetadata>, String> {
+    let stored = find_session(app, session_id)?;
+    if let Some(provider_id) = stored.as_ref().and_then(|s| s.provider_id.clone()) {
+        if worker::session_provider(session_id).is_none() {
+            worker::bind_session(session_id.to_string(), provider_id);
+        }
+    }
+    Ok(stored)
+}
+
+fn resume_info(stored: Option<SessionMetadata>) -> ResumeInfo {
+    match stored {
+        Some(session) => ResumeInfo {
+            workspace: Some(session.workspace_path),
+            mode_id: session.mode_id,
+            model_id: session.model_id,
+        },
+        None => ResumeInfo::default(),
+    }
+}
+
+#[tauri::command]
+async fn create_agent_session(
+    workspace: Option<String>,
+    provider: Option<String>,
+    model: Option<String>,
+) -> Result<String, String> {
+    worker::new_session(provider, workspace, model).await
 }
 
 #[tauri::command]
-async fn create_agent_session(workspace: Option<String>) -> Result<String, String> {
-    codex::new_codex_session(workspace).await
+async fn cancel_agent_turn(session_id: String) -> Result<(), String> {
+    worker::cancel_turn(session_id).await
+}
+
+/// Slash commands the session's agent advertised, filtered by name prefix
+#[tauri::command]
+fn list_available_commands(session_id: String, prefix: Option<String>) -> Vec<SlashCommand> {
+    commands::list(&session_id, prefix.as_deref())
+}
+
+/// Modes the session's agent offers; `None` if it has none or the session
+/// hasn't been resumed yet.
+#[tauri::command]
+fn list_session_modes(session_id: String) -> Option<SessionModes> {
+    modes::SESSION_MODES.get(&session_id)
+}
+
+#[tauri::command]
+async fn set_session_mode(
+    app: tauri::AppHandle,
+    session_id: String,
+    mode_id: String,
+) -> Result<(), String> {
+    let stored = stored_session(&app, &session_id)?;
+    worker::set_session_mode(session_id, mode_id, resume_info(stored)).await
+}
+
+/// Models the session's agent offers; `None` if it reports none or the
+/// session hasn't been resumed yet.
+#[tauri::command]
+fn list_session_models(session_id: String) -> Option<SessionModels> {
+    models::SESSION_MODELS.get(&session_id)
+}
+
+#[tauri::command]
+async fn set_session_model(
+    app: tauri::AppHandle,
+    session_id: String,
+    model_id: String,
+) -> Result<(), String> {
+    let stored = stored_session(&app, &session_id)?;
+    worker::set_session_model(session_id, model_id, resume_info(stored)).await
+}
+
+#[tauri::command]
+fn list_agent_providers() -> Vec<ProviderInfo> {
+    acp_agent_provider::list_providers()
 }
 
 #[tauri::command]
@@ -45,23 +166,51 @@ fn select_workspace_directory() -> Result<String, String> {
 #[tauri::command]
 async fn list_sessions(app: tauri::AppHandle) -> Result<Vec<SessionMetadata>, String> {
     let store = app.store(SESSION_STORE_KEY).map_err(|e| e.to_string())?;
-    match store.get("sessions") {
+    let sessions: Vec<SessionMetadata> = match store.get("sessions") {
         Some(sessions) => serde_json::from_value(sessions)
-            .map_err(|e| format!("Failed to parse sessions: {}", e)),
-        None => Ok(vec![]),
+            .map_err(|e| format!("Failed to parse sessions: {}", e))?,
+        None => vec![],
+    };
+
+    // Route prompts for restored sessions back to the agent they were created with
+    for session in &sessions {
+        if let Some(provider_id) = &session.provider_id {
+            if worker::session_provider(&session.id).is_none() {
+                worker::bind_session(session.id.clone(), provider_id.clone());
+            }
+        }
     }
+
+    Ok(sessions)
+}
+
+fn find_session(app: &tauri::AppHandle, session_id: &str) -> Result<Option<SessionMetadata>, String> {
+    let store = app.store(SESSION_STORE_KEY).map_err(|e| e.to_string())?;
+    let sessions: Vec<SessionMetadata> = store
+        .get("sessions")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    Ok(sessions.into_iter().find(|s| s.id == session_id))
 }
 
 #[tauri::command]
-async fn save_session(app: tauri::AppHandle, session: SessionMetadata) -> Result<(), String> {
+async fn save_session(app: tauri::AppHandle, mut session: SessionMetadata) -> Result<(), String> {
+    if session.provider_id.is_none() {
+        session.provider_id = worker::session_provider(&session.id);
+    }
+    if session.mode_id.is_none() {
+        session.mode_id = modes::SESSION_MODES.current(&session.id);
+    }
+    if session.model_id.is_none() {
+        session.model_id = models::SESSION_MODELS.current(&session.id);
+    }
+
     let store = app.store(SESSION_STORE_KEY).map_err(|e| e.to_string())?;
     let mut sessions: Vec<SessionMetadata> = store
         .get("sessions")
         .and_then(|v| serde_json::from_value(v).ok())
         .unwrap_or_default();
 
-    println!("sessions: {:?}", sessions);
-
     // Update existing or add new
     if let Some(pos) = sessions.iter().position(|s| s.id == session.id) {
         sessions[pos] = session;
@@ -84,32 +233,421 @@ async fn delete_session(app: tauri::AppHandle, session_id: String) -> Result<(),
     sessions.retain(|s| s.id != session_id);
 
     store.set("sessions", serde_json::to_value(sessions).unwrap());
+    store.save().map_err(|e| e.to_string())?;
+    delete_permission_history(&app, &session_id)?;
+    delete_approval_grants(&app, &session_id)?;
+    policy::forget(&session_id);
+    worker::release_session(&session_id);
+    turn_state::forget(&session_id);
+    modes::SESSION_MODES.forget(&session_id);
+    models::SESSION_MODELS.forget(&session_id);
+    commands::forget(&session_id);
+    staging::forget(&session_id);
+    file_versions::forget(&session_id);
+    buffers::forget_session(&session_id);
+    file_watcher::unwatch(&session_id);
+    file_write::delete_backups(&session_id)?;
+    checkpoints::delete(&session_id)?;
+    transcript::delete(&session_id)
+}
+
+/// Content of a stored attachment, base64-encoded
+#[tauri::command]
+fn get_attachment(hash: String) -> Result<String, String> {
+    use base64::Engine;
+    attachments::load(&hash).map(|bytes| base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+#[tauri::command]
+fn get_session_transcript(session_id: String) -> Result<Vec<TranscriptEntry>, String> {
+    transcript::load(&session_id)
+}
+
+#[tauri::command]
+fn get_session_transcript_page(
+    session_id: String,
+    offset: usize,
+    limit: usize,
+) -> Result<TranscriptPage, String> {
+    transcript::load_page(&session_id, offset, limit)
+}
+
+/// Tool calls and plan of the session's current or most recent turn
+#[tauri::command]
+fn get_turn_state(session_id: String) -> TurnState {
+    turn_state::get(&session_id)
+}
+
+/// Stores the unsaved contents of a file open in an editor, so the agent
+/// reads them instead of the file. Returns the buffer's version.
+#[tauri::command]
+fn update_editor_buffer(path: String, content: String) -> u64 {
+    buffers::update(Path::new(&path), content)
+}
+
+/// Forgets an editor buffer once it was saved or closed.
+#[tauri::command]
+fn close_editor_buffer(path: String) {
+    buffers::close(Path::new(&path))
+}
+
+#[tauri::command]
+fn list_editor_buffers() -> Vec<String> {
+    buffers::list()
+}
+
+/// Workspace states before each turn of the session, oldest first
+#[tauri::command]
+fn list_checkpoints(session_id: String) -> Result<Vec<Checkpoint>, String> {
+    checkpoints::list(&session_id)
+}
+
+/// Rolls the workspace back to the state before a turn. Returns the files
+/// that were changed.
+#[tauri::command]
+async fn restore_checkpoint(session_id: String, checkpoint_id: String) -> Result<Vec<String>, String> {
+    let files = checkpoints::restore(&session_id, &checkpoint_id).await?;
+    // Staged writes were made against the state that was just replaced.
+    staging::forget(&session_id);
+    Ok(files)
+}
+
+/// Writes held back for review in the session
+#[tauri::command]
+fn list_staged_changes(session_id: String) -> Vec<StagedDiff> {
+    staging::list(&session_id)
+}
+
+#[tauri::command]
+fn accept_staged_change(session_id: String, path: String) -> Result<(), String> {
+    staging::accept(&session_id, Path::new(&path))
+}
+
+#[tauri::command]
+fn reject_staged_change(session_id: String, path: String) -> Result<(), String> {
+    staging::reject(&session_id, Path::new(&path))
+}
+
+#[tauri::command]
+fn accept_staged_hunk(session_id: String, path: String, hunk: usize) -> Result<(), String> {
+    staging::accept_hunk(&session_id, Path::new(&path), hunk)
+}
+
+#[tauri::command]
+fn respond_to_permission(request_id: String, option_id: Option<String>) -> Result<(), String> {
+    permission::respond(&request_id, option_id)
+}
+
+#[tauri::command]
+async fn get_permission_history(
+    app: tauri::AppHandle,
+    session_id: String,
+) -> Result<Vec<PermissionDecision>, String> {
+    let store = app.store(PERMISSION_STORE_KEY).map_err(|e| e.to_string())?;
+    Ok(store
+        .get(&session_id)
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default())
+}
+
+fn record_permission_decision(
+    app: &tauri::AppHandle,
+    session_id: &str,
+    decision: PermissionDecision,
+) -> Result<(), String> {
+    let store = app.store(PERMISSION_STORE_KEY).map_err(|e| e.to_string())?;
+    let mut decisions: Vec<PermissionDecision> = store
+        .get(session_id)
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    decisions.push(decision);
+
+    store.set(session_id, serde_json::to_value(decisions).unwrap());
+    store.save().map_err(|e| e.to_string())
+}
+
+fn delete_permission_history(app: &tauri::AppHandle, session_id: &str) -> Result<(), String> {
+    let store = app.store(PERMISSION_STORE_KEY).map_err(|e| e.to_string())?;
+    if store.delete(session_id) {
+        store.save().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Updates a saved session, e.g. to remember the mode or model to restore when
+/// it is resumed. Unsaved sessions are skipped; `save_session` picks up their
+/// current mode and model.
+fn update_stored_session(
+    app: &tauri::AppHandle,
+    session_id: &str,
+    update: impl FnOnce(&mut SessionMetadata),
+) -> Result<(), String> {
+    let store = app.store(SESSION_STORE_KEY).map_err(|e| e.to_string())?;
+    let mut sessions: Vec<SessionMetadata> = store
+        .get("sessions")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    let Some(session) = sessions.iter_mut().find(|s| s.id == session_id) else {
+        return Ok(());
+    };
+    update(session);
+
+    store.set("sessions", serde_json::to_value(sessions).unwrap());
+    store.save().map_err(|e| e.to_string())
+}
+
+/// "Allow always" grants are stored per workspace, then per session id.
+fn record_approval_grant(
+    app: &tauri::AppHandle,
+    workspace: &str,
+    session_id: String,
+    rule: ApprovalRule,
+) -> Result<(), String> {
+    let store = app.store(APPROVAL_STORE_KEY).map_err(|e| e.to_string())?;
+    let mut grants: HashMap<String, Vec<ApprovalRule>> = store
+        .get(workspace)
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    let session_grants = grants.entry(session_id).or_default();
+    if !session_grants.contains(&rule) {
+        session_grants.push(rule);
+    }
+
+    store.set(workspace, serde_json::to_value(grants).unwrap());
+    store.save().map_err(|e| e.to_string())
+}
+
+fn delete_approval_grants(app: &tauri::AppHandle, session_id: &str) -> Result<(), String> {
+    let store = app.store(APPROVAL_STORE_KEY).map_err(|e| e.to_string())?;
+    let mut changed = false;
+    for (workspace, value) in store.entries() {
+        let mut grants: HashMap<String, Vec<ApprovalRule>> =
+            serde_json::from_value(value).unwrap_or_default();
+        if grants.remove(session_id).is_none() {
+            continue;
+        }
+        changed = true;
+        if grants.is_empty() {
+            store.delete(&workspace);
+        } else {
+            store.set(workspace, serde_json::to_value(grants).unwrap());
+        }
+    }
+    if changed {
+        store.save().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn load_approval_grants(app: &tauri::AppHandle) -> Result<(), String> {
+    let store = app.store(APPROVAL_STORE_KEY).map_err(|e| e.to_string())?;
+    for (_workspace, value) in store.entries() {
+        let grants: HashMap<String, Vec<ApprovalRule>> =
+            serde_json::from_value(value).unwrap_or_default();
+        for (session_id, rules) in grants {
+            policy::load_grants(session_id, rules);
+        }
+    }
+    Ok(())
+}
+
+/// Applies the workspace's privileged configuration, as shown in
+/// `workspace-approval-required`
+#[tauri::command]
+fn approve_workspace_config(
+    app: tauri::AppHandle,
+    workspace: String,
+    fingerprint: String,
+) -> Result<(), String> {
+    let workspace = workspace_config::workspace_key(Path::new(&workspace));
+    workspace_config::approve(workspace.clone(), fingerprint.clone());
+
+    let store = app.store(WORKSPACE_APPROVAL_STORE_KEY).map_err(|e| e.to_string())?;
+    store.set(workspace, serde_json::Value::String(fingerprint));
     store.save().map_err(|e| e.to_string())
 }
 
+fn load_workspace_approvals(app: &tauri::AppHandle) -> Result<(), String> {
+    let store = app.store(WORKSPACE_APPROVAL_STORE_KEY).map_err(|e| e.to_string())?;
+    let approvals = store
+        .entries()
+        .into_iter()
+        .filter_map(|(workspace, value)| Some((workspace, value.as_str()?.to_string())))
+        .collect();
+    workspace_config::load_approvals(approvals);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_settings() -> Settings {
+    settings::current()
+}
+
+#[tauri::command]
+async fn update_settings(app: tauri::AppHandle, settings: Settings) -> Result<(), String> {
+    settings.validate()?;
+    let store = app.store(SETTINGS_STORE_KEY).map_err(|e| e.to_string())?;
+    store.set("settings", serde_json::to_value(&settings).unwrap());
+    store.save().map_err(|e| e.to_string())?;
+    settings::replace(settings);
+    Ok(())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_store::Builder::new().build())
         .setup(|app| {
+            if let Some(saved) = app
+                .store(SETTINGS_STORE_KEY)?
+                .get("settings")
+                .and_then(|v| serde_json::from_value::<Settings>(v).ok())
+            {
+                // Broken rules fail closed when matched, so keep them.
+                if let Err(err) = saved.validate() {
+                    log::error!("Saved settings are invalid: {err}");
+                }
+                settings::replace(saved);
+            }
+
+            let transcript_dir = app.path().app_data_dir()?.join("transcripts");
+            attachments::init(transcript_dir.join("attachments"))?;
+            checkpoints::init(app.path().app_data_dir()?.join("checkpoints"))?;
+            match app.path().resource_dir() {
+                Ok(dir) => acp_agent_provider::init(dir),
+                Err(err) => log::warn!("No resource directory for bundled agents: {err}"),
+            }
+            file_write::init(app.path().app_data_dir()?.join("backups"))?;
+            transcript::init(transcript_dir)?;
+
             let handle = app.handle().clone();
+            if let Err(err) = load_approval_grants(&handle) {
+                log::error!("Failed to load approval grants: {err}");
+            }
+            if let Err(err) = load_workspace_approvals(&handle) {
+                log::error!("Failed to load workspace approvals: {err}");
+            }
             tauri::async_runtime::spawn(async move {
                 let mut rx = event_bus::EVENT_BUS.1.lock().unwrap().take().unwrap();
+                let mut transcripts = transcript::TranscriptWriter::new();
                 while let Some(event) = rx.recv().await {
+                    if let Err(err) = transcripts.record(&event) {
+                        log::error!("Failed to write transcript: {err}");
+                    }
                     match event {
                         event_bus::AgentEvent::Status { session_id, status } => {
                              let _ = handle.emit("agent-status", serde_json::json!({ "session_id": session_id, "status": status }));
                         }
+                        event_bus::AgentEvent::UserMessage { session_id, content, attachments } => {
+                             let _ = handle.emit("agent-user-message", serde_json::json!({ "session_id": session_id, "content": content, "attachments": attachments }));
+                        }
                         event_bus::AgentEvent::Chunk { session_id, content } => {
                              let _ = handle.emit("agent-chunk", serde_json::json!({ "session_id": session_id, "content": content }));
                         }
                         event_bus::AgentEvent::ThoughtChunk { session_id, content } => {
                              let _ = handle.emit("agent-thought-chunk", serde_json::json!({ "session_id": session_id, "content": content }));
                         }
-                        event_bus::AgentEvent::Update { session_id, update } => {
-                             let _ = handle.emit("agent-update", serde_json::json!({ "session_id": session_id, "update": update }));
+                        event_bus::AgentEvent::Update { session_id, update, replayed } => {
+                             let _ = handle.emit("agent-update", serde_json::json!({ "session_id": session_id, "update": update, "replayed": replayed }));
+                        }
+                        event_bus::AgentEvent::TurnComplete { session_id, stop_reason, model_id } => {
+                             let _ = handle.emit("agent-turn-complete", serde_json::json!({ "session_id": session_id, "stop_reason": stop_reason, "model_id": model_id }));
+                        }
+                        event_bus::AgentEvent::ToolCallChanged { session_id, tool_call } => {
+                             let _ = handle.emit("agent-tool-call", serde_json::json!({ "session_id": session_id, "tool_call": tool_call }));
+                        }
+                        event_bus::AgentEvent::PlanChanged { session_id, entries } => {
+                             let _ = handle.emit("agent-plan", serde_json::json!({ "session_id": session_id, "entries": entries }));
+                        }
+                        event_bus::AgentEvent::ModeChanged { session_id, mode_id } => {
+                             if let Err(err) = update_stored_session(&handle, &session_id, |s| s.mode_id = Some(mode_id.clone())) {
+                                 log::error!("Failed to record session mode: {err}");
+                             }
+                             let _ = handle.emit("agent-mode-changed", serde_json::json!({ "session_id": session_id, "mode_id": mode_id }));
+                        }
+                        event_bus::AgentEvent::ModelChanged { session_id, model_id } => {
+                             if let Err(err) = update_stored_session(&handle, &session_id, |s| s.model_id = Some(model_id.clone())) {
+                                 log::error!("Failed to record session model: {err}");
+                             }
+                             let _ = handle.emit("agent-model-changed", serde_json::json!({ "session_id": session_id, "model_id": model_id }));
+                        }
+                        event_bus::AgentEvent::EditConflict { session_id, path, reason } => {
+                             let _ = handle.emit("agent-edit-conflict", serde_json::json!({ "session_id": session_id, "path": path, "reason": reason }));
+                        }
+                        event_bus::AgentEvent::BufferChanged { session_id, path, content, version } => {
+                             let _ = handle.emit("agent-buffer-changed", serde_json::json!({ "session_id": session_id, "path": path, "content": content, "version": version }));
+                        }
+                        event_bus::AgentEvent::CheckpointRecorded { session_id, checkpoint } => {
+                             let _ = handle.emit("agent-checkpoint", serde_json::json!({ "session_id": session_id, "checkpoint": checkpoint }));
+                        }
+                        event_bus::AgentEvent::WorkspaceFileChanged { workspace, path, kind, source } => {
+                             let _ = handle.emit("workspace-file-changed", serde_json::json!({ "workspace": workspace, "path": path, "kind": kind, "source": source }));
+                        }
+                        event_bus::AgentEvent::TurnFilesChanged { session_id, files } => {
+                             let _ = handle.emit("agent-turn-files-changed", serde_json::json!({ "session_id": session_id, "files": files }));
+                        }
+                        event_bus::AgentEvent::WorkspaceConfigChanged { workspace, error } => {
+                             let _ = handle.emit("workspace-config-changed", serde_json::json!({ "workspace": workspace, "error": error }));
+                        }
+                        event_bus::AgentEvent::WorkspaceApprovalRequired { workspace, config, fingerprint } => {
+                             let _ = handle.emit("workspace-approval-required", serde_json::json!({ "workspace": workspace, "config": config, "fingerprint": fingerprint }));
+                        }
+                        event_bus::AgentEvent::HookFinished { session_id, command, exit_code, timed_out, output } => {
+                             let _ = handle.emit("agent-hook-finished", serde_json::json!({ "session_id": session_id, "command": command, "exit_code": exit_code, "timed_out": timed_out, "output": output }));
+                        }
+                        event_bus::AgentEvent::StagedChanged { session_id, path, diff } => {
+                             let _ = handle.emit("agent-staged-change", serde_json::json!({ "session_id": session_id, "path": path, "diff": diff }));
+                        }
+                        event_bus::AgentEvent::CommandsAvailable { session_id, commands } => {
+                             let _ = handle.emit("agent-available-commands", serde_json::json!({ "session_id": session_id, "commands": commands }));
+                        }
+                        event_bus::AgentEvent::HistoryChunk { session_id, role, content } => {
+                             let _ = handle.emit("agent-history-chunk", serde_json::json!({ "session_id": session_id, "role": role, "content": content }));
+                        }
+                        event_bus::AgentEvent::SessionResumed { session_id, agent_session_id, restored, reason } => {
+                             let _ = handle.emit("agent-session-resumed", serde_json::json!({ "session_id": session_id, "agent_session_id": agent_session_id, "restored": restored, "reason": reason }));
+                        }
+                        event_bus::AgentEvent::TerminalStarted { session_id, terminal_id, command, cwd } => {
+                             let _ = handle.emit("agent-terminal-started", serde_json::json!({ "session_id": session_id, "terminal_id": terminal_id, "command": command, "cwd": cwd }));
+                        }
+                        event_bus::AgentEvent::TerminalOutput { session_id, terminal_id, chunk } => {
+                             let _ = handle.emit("agent-terminal-output", serde_json::json!({ "session_id": session_id, "terminal_id": terminal_id, "chunk": chunk }));
+                        }
+                        event_bus::AgentEvent::TerminalExited { session_id, terminal_id, exit_code, signal } => {
+                             let _ = handle.emit("agent-terminal-exited", serde_json::json!({ "session_id": session_id, "terminal_id": terminal_id, "exit_code": exit_code, "signal": signal }));
+                        }
+                        event_bus::AgentEvent::PermissionRequest { session_id, request_id, tool_call, options, timeout_secs, rule } => {
+                             let _ = handle.emit("agent-permission-request", serde_json::json!({ "session_id": session_id, "request_id": request_id, "tool_call": tool_call, "options": options, "timeout_secs": timeout_secs, "rule": rule }));
+                        }
+                        event_bus::AgentEvent::PermissionResolved { session_id, request_id, tool_call_id, title, option_id, resolution, rule } => {
+                             let _ = handle.emit("agent-permission-resolved", serde_json::json!({ "session_id": session_id, "request_id": request_id, "option_id": option_id, "resolution": resolution, "rule": rule }));
+                             let decision = PermissionDecision {
+                                 request_id,
+                                 tool_call_id,
+                                 title,
+                                 option_id,
+                                 resolution,
+                                 rule,
+                                 decided_at: session_store::unix_now(),
+                             };
+                             if let Err(err) = record_permission_decision(&handle, &session_id, decision) {
+                                 log::error!("Failed to record permission decision: {err}");
+                             }
+                        }
+                        event_bus::AgentEvent::ApprovalGranted { session_id, workspace, rule } => {
+                             if let Err(err) = record_approval_grant(&handle, &workspace, session_id, rule) {
+                                 log::error!("Failed to record approval grant: {err}");
+                             }
+                        }
+                        event_bus::AgentEvent::AgentExited { provider_id, session_ids, exit_code, signal, stderr_tail, restart_in_secs } => {
+                             let _ = handle.emit("agent-process-exited", serde_json::json!({ "provider_id": provider_id, "session_ids": session_ids, "exit_code": exit_code, "signal": signal, "stderr_tail": stderr_tail, "restart_in_secs": restart_in_secs }));
+                        }
+                        event_bus::AgentEvent::AgentRestarted { provider_id, session_ids, reattached } => {
+                             let _ = handle.emit("agent-process-restarted", serde_json::json!({ "provider_id": provider_id, "session_ids": session_ids, "reattached": reattached }));
                         }
                     }
                 }
@@ -120,10 +658,35 @@ pub fn run() {
             greet,
             send_agent_message,
             create_agent_session,
+            cancel_agent_turn,
+            list_agent_providers,
+            list_available_commands,
+            list_session_modes,
+            set_session_mode,
+            list_session_models,
+            set_session_model,
+            respond_to_permission,
+            get_permission_history,
+            approve_workspace_config,
+            get_settings,
+            update_settings,
             select_workspace_directory,
             list_sessions,
             save_session,
-            delete_session
+            delete_session,
+            get_session_transcript,
+            get_session_transcript_page,
+            get_turn_state,
+            update_editor_buffer,
+            close_editor_buffer,
+            list_editor_buffers,
+            list_checkpoints,
+            restore_checkpoint,
+            list_staged_changes,
+            accept_staged_change,
+            reject_staged_change,
+            accept_staged_hunk,
+            get_attachment
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
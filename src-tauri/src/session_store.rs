@@ -1,6 +1,17 @@
 use serde::{Deserialize, Serialize};
 
 pub const SESSION_STORE_KEY: &str = "sessions.dat";
+pub const PERMISSION_STORE_KEY: &str = "permissions.dat";
+pub const APPROVAL_STORE_KEY: &str = "approvals.dat";
+pub const WORKSPACE_APPROVAL_STORE_KEY: &str = "workspace_approvals.dat";
+
+/// Current Unix timestamp in seconds
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
 
 /// Metadata for a session stored in the Tauri store
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -8,29 +19,50 @@ pub struct SessionMetadata {
     pub id: String,
     pub name: String,
     pub workspace_path: String,
-    pub created_at: u64, // Unix timestamp in seconds
+    pub created_at: u64,  // Unix timestamp in seconds
     pub last_active: u64, // Unix timestamp in seconds
+    #[serde(default)]
+    pub provider_id: Option<String>, // Agent provider the session is bound to
+    #[serde(default)]
+    pub mode_id: Option<String>, // Session mode the agent was last switched to
+    #[serde(default)]
+    pub model_id: Option<String>, // Model the session last used
 }
 
 impl SessionMetadata {
-    pub fn new(id: String, name: String, workspace_path: String) -> Self {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    pub fn new(
+        id: String,
+        name: String,
+        workspace_path: String,
+        provider_id: Option<String>,
+    ) -> Self {
+        let now = unix_now();
         Self {
             id,
             name,
             workspace_path,
             created_at: now,
             last_active: now,
+            provider_id,
+            mode_id: None,
+            model_id: None,
         }
     }
 
     pub fn update_last_active(&mut self) {
-        self.last_active = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        self.last_active = unix_now();
     }
 }
+
+/// A permission prompt and how it was answered, keyed by session id in the permission store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionDecision {
+    pub request_id: String,
+    pub tool_call_id: String,
+    pub title: Option<String>,
+    pub option_id: Option<String>, // None when rejected without a matching option or cancelled
+    pub resolution: String, // "selected", "rejected", "timed_out", "cancelled", "auto_allowed" or "auto_denied"
+    #[serde(default)]
+    pub rule: Option<String>, // Approval rule that decided or escalated the request
+    pub decided_at: u64,    // Unix timestamp in seconds
+}
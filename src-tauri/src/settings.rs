@@ -0,0 +1,73 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::acp_client::{
+    file_access::{self, FileAccessRules},
+    mcp::McpServerConfig,
+    policy::ApprovalPolicy,
+};
+
+pub const SETTINGS_STORE_KEY: &str = "settings.json";
+
+/// Application-wide settings stored in the Tauri store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Seconds to wait for the user to answer a permission prompt before rejecting it
+    pub permission_timeout_secs: u64,
+    /// Approval rules applied after the workspace's own rules
+    pub approval: ApprovalPolicy,
+    /// Run every session in its own agent process instead of one process per provider
+    pub isolate_sessions: bool,
+    /// Model selected for new sessions, by provider id
+    pub default_models: HashMap<String, String>,
+    /// Sensitive files the agent may not read or write
+    pub file_access: FileAccessRules,
+    /// Hold agent writes for review instead of writing them to disk
+    pub review_writes: bool,
+    /// Largest file, or range of lines, the agent can read at once
+    pub max_read_bytes: u64,
+    /// MCP servers passed to every session; a workspace can replace them by name
+    pub mcp_servers: Vec<McpServerConfig>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            permission_timeout_secs: 120,
+            approval: ApprovalPolicy::default(),
+            isolate_sessions: false,
+            default_models: HashMap::new(),
+            file_access: FileAccessRules::default(),
+            review_writes: false,
+            max_read_bytes: 10 * 1024 * 1024,
+            mcp_servers: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Checks the approval rules and deny-lists, so that a broken pattern is
+    /// rejected instead of silently never matching.
+    pub fn validate(&self) -> Result<(), String> {
+        self.approval
+            .validate()
+            .map_err(|err| format!("Invalid approval rules: {err}"))?;
+        file_access::validate(&self.file_access.deny, "settings")?;
+        file_access::validate(&self.file_access.deny_write, "settings")
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref SETTINGS: RwLock<Settings> = RwLock::new(Settings::default());
+}
+
+/// Snapshot of the current settings.
+pub fn current() -> Settings {
+    SETTINGS.read().unwrap().clone()
+}
+
+pub fn replace(settings: Settings) {
+    *SETTINGS.write().unwrap() = settings;
+}
@@ -0,0 +1,424 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{attachments::StoredAttachment, event_bus::AgentEvent};
+
+static TRANSCRIPT_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the directory transcripts are stored in. Called once during setup.
+pub fn init(dir: PathBuf) -> Result<(), String> {
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create transcript directory: {}", e))?;
+    let _ = TRANSCRIPT_DIR.set(dir);
+    Ok(())
+}
+
+fn transcript_path(session_id: &str) -> Result<PathBuf, String> {
+    let dir = TRANSCRIPT_DIR
+        .get()
+        .ok_or_else(|| "Transcript storage is not initialized".to_string())?;
+    Ok(dir.join(format!("{}.jsonl", file_stem(session_id))))
+}
+
+/// A file name for per-session data. Session ids come from the agent; keep
+/// them from escaping the directory. Every byte other than a lowercase ASCII
+/// letter, a digit or `-` is escaped as `_xx`, so distinct ids never share a
+/// name, even on case-insensitive file systems.
+pub(crate) fn file_stem(session_id: &str) -> String {
+    let mut stem = String::with_capacity(session_id.len());
+    for byte in session_id.bytes() {
+        if byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-' {
+            stem.push(byte as char);
+        } else {
+            stem.push_str(&format!("_{byte:02x}"));
+        }
+    }
+    stem
+}
+
+/// One line of a session transcript
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    pub timestamp: u64, // Unix timestamp in milliseconds
+    #[serde(flatten)]
+    pub record: TranscriptRecord,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TranscriptRecord {
+    UserMessage {
+        content: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        attachments: Vec<StoredAttachment>,
+    },
+    AgentMessage {
+        content: String,
+    },
+    Thought {
+        content: String,
+    },
+    /// Raw ACP `tool_call`, `tool_call_update` and `plan` updates
+    Update {
+        update: serde_json::Value,
+    },
+    Permission {
+        request_id: String,
+        title: Option<String>,
+        option_id: Option<String>,
+        resolution: String,
+        rule: Option<String>,
+    },
+    TurnComplete {
+        stop_reason: String,
+        /// Model the turn ran on, if the agent reports models
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model_id: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptPage {
+    pub entries: Vec<TranscriptEntry>,
+    pub offset: usize,
+    pub total: usize,
+}
+
+/// Appends agent events to per-session JSONL files. Streamed chunks are
+/// buffered and written as a single message once the stream moves on.
+#[derive(Default)]
+pub struct TranscriptWriter {
+    pending: HashMap<String, (ChunkKind, String)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ChunkKind {
+    Agent,
+    Thought,
+}
+
+impl TranscriptWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, event: &AgentEvent) -> Result<(), String> {
+        let (session_id, record) = match event {
+            AgentEvent::UserMessage {
+                session_id,
+                content,
+                attachments,
+            } => (
+                session_id,
+                TranscriptRecord::UserMessage {
+                    content: content.clone(),
+                    attachments: attachments.clone(),
+                },
+            ),
+            AgentEvent::Chunk {
+                session_id,
+                content,
+            } => return self.buffer(session_id, ChunkKind::Agent, content),
+            AgentEvent::ThoughtChunk {
+                session_id,
+                content,
+            } => return self.buffer(session_id, ChunkKind::Thought, content),
+            AgentEvent::Update {
+                session_id,
+                update,
+                replayed: false,
+            } => match update.as_ref() {
+                agent_client_protocol::SessionUpdate::ToolCall(_)
+                | agent_client_protocol::SessionUpdate::ToolCallUpdate(_)
+                | agent_client_protocol::SessionUpdate::Plan(_) => (
+                    session_id,
+                    TranscriptRecord::Update {
+                        update: serde_json::to_value(update).map_err(|e| e.to_string())?,
+                    },
+                ),
+                _ => return Ok(()),
+            },
+            AgentEvent::PermissionResolved {
+                session_id,
+                request_id,
+                title,
+                option_id,
+                resolution,
+                rule,
+                ..
+            } => (
+                session_id,
+                TranscriptRecord::Permission {
+                    request_id: request_id.clone(),
+                    title: title.clone(),
+                    option_id: option_id.clone(),
+                    resolution: resolution.clone(),
+                    rule: rule.clone(),
+                },
+            ),
+            AgentEvent::TurnComplete {
+                session_id,
+                stop_reason,
+                model_id,
+            } => (
+                session_id,
+                TranscriptRecord::TurnComplete {
+                    stop_reason: stop_reason.clone(),
+                    model_id: model_id.clone(),
+                },
+            ),
+            _ => return Ok(()),
+        };
+
+        self.flush(session_id)?;
+        append(session_id, &entry(record))
+    }
+
+    fn buffer(&mut self, session_id: &str, kind: ChunkKind, content: &str) -> Result<(), String> {
+        if self
+            .pending
+            .get(session_id)
+            .is_some_and(|(pending_kind, _)| *pending_kind != kind)
+        {
+            self.flush(session_id)?;
+        }
+
+        self.pending
+            .entry(session_id.to_string())
+            .or_insert_with(|| (kind, String::new()))
+            .1
+            .push_str(content);
+        Ok(())
+    }
+
+    /// Writes out the buffered message of a session, if any.
+    pub fn flush(&mut self, session_id: &str) -> Result<(), String> {
+        let record = match self.pending.remove(session_id) {
+            Some((ChunkKind::Agent, content)) => TranscriptRecord::AgentMessage { content },
+            Some((ChunkKind::Thought, content)) => TranscriptRecord::Thought { content },
+            None => return Ok(()),
+        };
+        append(session_id, &entry(record))
+    }
+}
+
+fn entry(record: TranscriptRecord) -> TranscriptEntry {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    TranscriptEntry { timestamp, record }
+}
+
+fn append(session_id: &str, entry: &TranscriptEntry) -> Result<(), String> {
+    let path = transcript_path(session_id)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open transcript: {}", e))?;
+
+    let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    writeln!(file, "{line}").map_err(|e| format!("Failed to write transcript: {}", e))
+}
+
+fn open(path: &Path) -> Result<Option<BufReader<fs::File>>, String> {
+    match fs::File::open(path) {
+        Ok(file) => Ok(Some(BufReader::new(file))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to open transcript: {}", e)),
+    }
+}
+
+pub fn load(session_id: &str) -> Result<Vec<TranscriptEntry>, String> {
+    let Some(reader) = open(&transcript_path(session_id)?)? else {
+        return Ok(vec![]);
+    };
+
+    // Skip lines that fail to parse, e.g. a partial line after a crash.
+    Ok(reader
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect())
+}
+
+/// Returns up to `limit` entries starting at `offset`, oldest first. Only the
+/// entries of the page are kept in memory.
+pub fn load_page(session_id: &str, offset: usize, limit: usize) -> Result<TranscriptPage, String> {
+    let mut page = TranscriptPage {
+        entries: Vec::new(),
+        offset,
+        total: 0,
+    };
+    let Some(reader) = open(&transcript_path(session_id)?)? else {
+        return Ok(page);
+    };
+
+    let end = offset.saturating_add(limit);
+    for line in reader.lines().map_while(Result::ok) {
+        if (offset..end).contains(&page.total) {
+            match serde_json::from_str(&line) {
+                Ok(entry) => page.entries.push(entry),
+                Err(_) => continue,
+            }
+        } else if serde_json::from_str::<serde::de::IgnoredAny>(&line).is_err() {
+            continue;
+        }
+        page.total += 1;
+    }
+    Ok(page)
+}
+
+pub fn delete(session_id: &str) -> Result<(), String> {
+    match fs::remove_file(transcript_path(session_id)?) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("Failed to delete transcript: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init_dir() {
+        let dir =
+            std::env::temp_dir().join(format!("open-cowork-transcript-{}", std::process::id()));
+        init(dir).unwrap();
+    }
+
+    fn chunk(session_id: &str, content: &str) -> AgentEvent {
+        AgentEvent::Chunk {
+            session_id: session_id.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    fn thought(session_id: &str, content: &str) -> AgentEvent {
+        AgentEvent::ThoughtChunk {
+            session_id: session_id.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    fn turn_complete(session_id: &str) -> AgentEvent {
+        AgentEvent::TurnComplete {
+            session_id: session_id.to_string(),
+            stop_reason: "end_turn".to_string(),
+            model_id: None,
+        }
+    }
+
+    fn contents(entries: &[TranscriptEntry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|entry| match &entry.record {
+                TranscriptRecord::AgentMessage { content } => format!("agent: {content}"),
+                TranscriptRecord::Thought { content } => format!("thought: {content}"),
+                TranscriptRecord::TurnComplete { stop_reason, .. } => {
+                    format!("done: {stop_reason}")
+                }
+                other => format!("{other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn file_stems_are_distinct_and_safe() {
+        let ids = ["a.b", "a:b", "a_b", "a_2eb", "A-b", "a-b", "../x"];
+        let stems: Vec<_> = ids.iter().map(|id| file_stem(id)).collect();
+        for (i, stem) in stems.iter().enumerate() {
+            assert!(stem
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_'));
+            assert!(!stems[..i].contains(stem), "{stem}");
+        }
+        assert_eq!(file_stem("0f3c-9a"), "0f3c-9a");
+    }
+
+    #[test]
+    fn writer_coalesces_chunks_until_the_stream_moves_on() {
+        init_dir();
+        let session_id = "coalesce";
+        delete(session_id).unwrap();
+        let mut writer = TranscriptWriter::new();
+
+        for event in [
+            thought(session_id, "let me "),
+            thought(session_id, "think"),
+            chunk(session_id, "Hello, "),
+            chunk("coalesce-other", "elsewhere"),
+            chunk(session_id, "world"),
+        ] {
+            writer.record(&event).unwrap();
+        }
+        // The message is still buffered
+        assert_eq!(
+            contents(&load(session_id).unwrap()),
+            ["thought: let me think"]
+        );
+
+        writer.record(&turn_complete(session_id)).unwrap();
+        writer.record(&chunk(session_id, "later")).unwrap();
+        writer.flush(session_id).unwrap();
+        assert_eq!(
+            contents(&load(session_id).unwrap()),
+            [
+                "thought: let me think",
+                "agent: Hello, world",
+                "done: end_turn",
+                "agent: later"
+            ]
+        );
+
+        writer.flush("coalesce-other").unwrap();
+        assert_eq!(
+            contents(&load("coalesce-other").unwrap()),
+            ["agent: elsewhere"]
+        );
+        delete(session_id).unwrap();
+        delete("coalesce-other").unwrap();
+    }
+
+    #[test]
+    fn pages_skip_to_the_offset_and_ignore_broken_lines() {
+        init_dir();
+        let session_id = "paging";
+        delete(session_id).unwrap();
+        let mut writer = TranscriptWriter::new();
+        for i in 0..5 {
+            writer.record(&chunk(session_id, &i.to_string())).unwrap();
+            writer.record(&turn_complete(session_id)).unwrap();
+        }
+        // A partial line after a crash
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(transcript_path(session_id).unwrap())
+            .unwrap();
+        write!(file, "{{\"timestamp\":1,\"type\":\"agent_me").unwrap();
+
+        let page = load_page(session_id, 3, 4).unwrap();
+        assert_eq!(page.total, 10);
+        assert_eq!(page.offset, 3);
+        assert_eq!(
+            contents(&page.entries),
+            ["done: end_turn", "agent: 2", "done: end_turn", "agent: 3"]
+        );
+
+        let page = load_page(session_id, 8, 100).unwrap();
+        assert_eq!(contents(&page.entries), ["agent: 4", "done: end_turn"]);
+        assert!(load_page(session_id, 20, 5).unwrap().entries.is_empty());
+
+        let missing = load_page("paging-missing", 0, 10).unwrap();
+        assert_eq!((missing.entries.len(), missing.total), (0, 0));
+        delete(session_id).unwrap();
+    }
+}
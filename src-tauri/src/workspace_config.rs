@@ -0,0 +1,376 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    acp_agent_provider::find_provider,
+    acp_client::{
+        file_access,
+        mcp::{self, McpServerConfig},
+        policy::{ApprovalPolicy, ApprovalRule, PolicyAction},
+    },
+    event_bus::{self, AgentEvent},
+};
+
+/// Per-workspace configuration file, looked up at the workspace root
+pub const CONFIG_FILE_NAME: &str = ".open-cowork.toml";
+
+/// Seconds a post-turn hook may run when it sets no `timeout_secs`
+const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkspaceConfig {
+    pub agent: AgentConfig,
+    pub approval: ApprovalPolicy,
+    pub file_access: FileAccessConfig,
+    pub mcp_servers: Vec<McpServerConfig>,
+    /// Set for the agent process, its terminals and the hooks
+    pub env: BTreeMap<String, String>,
+    pub hooks: HooksConfig,
+}
+
+/// Defaults for new sessions in the workspace
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentConfig {
+    pub provider: Option<String>,
+    /// Passed to the agent after the provider's own arguments, when the
+    /// session uses `provider` or no provider is set
+    pub args: Vec<String>,
+    pub model: Option<String>,
+    pub mode: Option<String>,
+}
+
+/// Patterns added after the global `file_access` lists
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileAccessConfig {
+    pub deny: Vec<String>,
+    pub deny_write: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Commands run in the workspace after every completed turn
+    pub post_turn: Vec<Hook>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hook {
+    pub command: String,
+    #[serde(default = "default_hook_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_hook_timeout() -> u64 {
+    DEFAULT_HOOK_TIMEOUT_SECS
+}
+
+impl WorkspaceConfig {
+    /// Checks what the TOML schema alone can't, collecting every problem.
+    fn validate(&self, workspace: &Path) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if let Some(provider) = &self.agent.provider {
+            if let Err(err) = find_provider(provider) {
+                errors.push(format!("agent.provider: {err}"));
+            }
+        }
+        if let Err(err) = self.approval.validate() {
+            errors.push(format!("approval: {err}"));
+        }
+        for (field, patterns) in [
+            ("file_access.deny", &self.file_access.deny),
+            ("file_access.deny_write", &self.file_access.deny_write),
+        ] {
+            if let Err(err) = file_access::validate(patterns, CONFIG_FILE_NAME) {
+                errors.push(format!("{field}: {err}"));
+            }
+        }
+        if let Err(err) = mcp::validate(&self.mcp_servers, workspace) {
+            errors.push(format!("mcp_servers: {err}"));
+        }
+        for name in self.env.keys() {
+            if name.is_empty() || name.contains(['=', '\0']) {
+                errors.push(format!("env: invalid variable name '{name}'"));
+            }
+        }
+        for (index, hook) in self.hooks.post_turn.iter().enumerate() {
+            if hook.command.trim().is_empty() {
+                errors.push(format!("hooks.post_turn[{index}]: command is empty"));
+            }
+            if hook.timeout_secs == 0 {
+                errors.push(format!(
+                    "hooks.post_turn[{index}]: timeout_secs must be positive"
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The parts that need the user's approval
+    pub fn privileged(&self) -> PrivilegedConfig {
+        PrivilegedConfig {
+            agent_args: self.agent.args.clone(),
+            env: self.env.clone(),
+            mcp_servers: self.mcp_servers.clone(),
+            allow_rules: self
+                .approval
+                .rules
+                .iter()
+                .filter(|rule| rule.action == PolicyAction::Allow)
+                .cloned()
+                .collect(),
+            hooks: self.hooks.post_turn.clone(),
+        }
+    }
+
+    /// The configuration without the parts that need approval
+    fn unprivileged(mut self) -> Self {
+        self.agent.args.clear();
+        self.env.clear();
+        self.mcp_servers.clear();
+        self.approval
+            .rules
+            .retain(|rule| rule.action != PolicyAction::Allow);
+        self.hooks.post_turn.clear();
+        self
+    }
+}
+
+/// What a cloned repository could use to run code or to loosen permissions.
+/// None of it applies until the user approved it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PrivilegedConfig {
+    pub agent_args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub mcp_servers: Vec<McpServerConfig>,
+    pub allow_rules: Vec<ApprovalRule>,
+    pub hooks: Vec<Hook>,
+}
+
+impl PrivilegedConfig {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Identifies what the user approved; any change asks for approval again.
+    pub fn fingerprint(&self) -> String {
+        let content = serde_json::to_vec(self).unwrap();
+        Sha256::digest(content)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+impl Default for PrivilegedConfig {
+    fn default() -> Self {
+        WorkspaceConfig::default().privileged()
+    }
+}
+
+/// A parsed file and the file state it was read from
+struct Cached {
+    modified: Option<SystemTime>,
+    len: u64,
+    config: WorkspaceConfig,
+}
+
+lazy_static::lazy_static! {
+    static ref CACHE: Mutex<HashMap<PathBuf, Cached>> = Mutex::new(HashMap::new());
+    /// Last outcome reported by `reload`, per workspace
+    static ref REPORTED: Mutex<HashMap<PathBuf, Result<WorkspaceConfig, String>>> = Mutex::new(HashMap::new());
+    /// Fingerprint of the privileged configuration the user approved, by
+    /// canonical workspace path
+    static ref APPROVED: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+/// Loads and validates the workspace configuration, or returns the defaults
+/// when the file is absent. The file is parsed again whenever it changed, so
+/// edits take effect without restarting.
+pub fn load(workspace: &Path) -> Result<WorkspaceConfig, String> {
+    let path = workspace.join(CONFIG_FILE_NAME);
+    let metadata = match fs::metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            CACHE.lock().unwrap().remove(&path);
+            return Ok(WorkspaceConfig::default());
+        }
+        Err(err) => return Err(format!("Failed to read {}: {err}", path.display())),
+    };
+    let modified = metadata.modified().ok();
+    if let Some(cached) = CACHE.lock().unwrap().get(&path) {
+        if cached.modified == modified && cached.len == metadata.len() {
+            return Ok(cached.config.clone());
+        }
+    }
+
+    let content = fs::read_to_string(&path)
+        .map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+    let config: WorkspaceConfig =
+        toml::from_str(&content).map_err(|err| format!("Invalid {}: {err}", path.display()))?;
+    config
+        .validate(workspace)
+        .map_err(|errors| format!("Invalid {}:\n- {}", path.display(), errors.join("\n- ")))?;
+
+    CACHE.lock().unwrap().insert(
+        path,
+        Cached {
+            modified,
+            len: metadata.len(),
+            config: config.clone(),
+        },
+    );
+    Ok(config)
+}
+
+/// Like [`load`], but leaves out the privileged parts until the user
+/// approved them.
+pub fn load_approved(workspace: &Path) -> Result<WorkspaceConfig, String> {
+    let config = load(workspace)?;
+    Ok(if is_approved(workspace, &config) {
+        config
+    } else {
+        config.unprivileged()
+    })
+}
+
+/// Key under which a workspace's approval is stored
+pub fn workspace_key(workspace: &Path) -> String {
+    workspace
+        .canonicalize()
+        .unwrap_or_else(|_| workspace.to_path_buf())
+        .display()
+        .to_string()
+}
+
+/// Restores approvals persisted by an earlier run.
+pub fn load_approvals(approvals: HashMap<String, String>) {
+    APPROVED.lock().unwrap().extend(approvals);
+}
+
+/// Applies the privileged configuration with `fingerprint` from now on.
+pub fn approve(workspace_key: String, fingerprint: String) {
+    APPROVED.lock().unwrap().insert(workspace_key, fingerprint);
+}
+
+/// Whether the configuration has nothing privileged, or the user approved it
+pub fn is_approved(workspace: &Path, config: &WorkspaceConfig) -> bool {
+    let privileged = config.privileged();
+    privileged.is_empty()
+        || APPROVED.lock().unwrap().get(&workspace_key(workspace))
+            == Some(&privileged.fingerprint())
+}
+
+/// Like [`is_approved`], but also asks the UI to have the user approve the
+/// privileged configuration.
+pub fn request_approval(workspace: &Path, config: &WorkspaceConfig) -> bool {
+    if is_approved(workspace, config) {
+        return true;
+    }
+    let privileged = config.privileged();
+    event_bus::emit_event(AgentEvent::WorkspaceApprovalRequired {
+        workspace: workspace_key(workspace),
+        fingerprint: privileged.fingerprint(),
+        config: Box::new(privileged),
+    });
+    false
+}
+
+/// Reloads the configuration after the file changed and tells the UI when
+/// the outcome differs from the last one it saw. A changed privileged
+/// configuration must be approved again.
+pub fn reload(workspace: &Path) {
+    let result = load(workspace);
+    let mut reported = REPORTED.lock().unwrap();
+    if reported.get(workspace) == Some(&result) {
+        return;
+    }
+    reported.insert(workspace.to_path_buf(), result.clone());
+    drop(reported);
+
+    if let Ok(config) = &result {
+        request_approval(workspace, config);
+    }
+    event_bus::emit_event(AgentEvent::WorkspaceConfigChanged {
+        workspace: workspace.display().to_string(),
+        error: result.err(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn privileged_changes_need_approval_again() {
+        let workspace = std::env::temp_dir().join(format!(
+            "open-cowork-workspace-config-{}",
+            std::process::id()
+        ));
+        let mut config = WorkspaceConfig::default();
+        config.agent.model = Some("fast".to_string());
+        config.approval.rules.push(ApprovalRule {
+            name: None,
+            action: PolicyAction::Deny,
+            tool_kinds: Vec::new(),
+            paths: Vec::new(),
+            commands: vec!["rm *".to_string()],
+        });
+        assert!(request_approval(&workspace, &config));
+
+        config.hooks.post_turn.push(Hook {
+            command: "cargo fmt".to_string(),
+            timeout_secs: 60,
+        });
+        assert!(!request_approval(&workspace, &config));
+        approve(workspace_key(&workspace), config.privileged().fingerprint());
+        assert!(request_approval(&workspace, &config));
+
+        let changes: [fn(&mut WorkspaceConfig); 4] = [
+            |config| {
+                config
+                    .env
+                    .insert("NODE_OPTIONS".to_string(), "--require ./x.js".to_string());
+            },
+            |config| config.agent.args.push("--yolo".to_string()),
+            |config| {
+                config.mcp_servers.push(McpServerConfig {
+                    name: "tools".to_string(),
+                    transport: Default::default(),
+                    command: Some("./bin/tools".to_string()),
+                    args: Vec::new(),
+                    env: BTreeMap::new(),
+                    url: None,
+                    headers: BTreeMap::new(),
+                })
+            },
+            |config| config.approval.rules[0].action = PolicyAction::Allow,
+        ];
+        for change in changes {
+            let mut changed = config.clone();
+            change(&mut changed);
+            assert!(!is_approved(&workspace, &changed));
+
+            let unprivileged = changed.clone().unprivileged();
+            assert_eq!(unprivileged.privileged(), PrivilegedConfig::default());
+            assert_eq!(unprivileged.agent.model, changed.agent.model);
+        }
+    }
+}